@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use serde::{Serialize, Deserialize, Deserializer};
+use crate::index::{index_path, Index};
+use crate::media::{Media, MediaFile};
+use crate::musicfile::MusicFile;
+use crate::scan::{rescan, ScanReport};
+
+
+// Version courante du format de fichier du catalogue.
+// Elle doit être incrémentée à chaque changement incompatible du format JSON.
+// Version 2 : les champs texte conservent leurs espaces (ils étaient remplacés par des "_")
+// Version 3 : ajout des champs lus dans les tags ID3v2 (album_artist, track, disc, composer, comment)
+// Version 4 : chaque média est enregistré avec son type (champ "kind")
+// Version 5 : ajout des propriétés du flux audio (bitrate, vbr, mpeg_version)
+pub const CATALOG_VERSION: u32 = 5;
+
+
+// Catalogue persistant des médias scannés : il conserve le résultat d'un scan
+// afin de pouvoir effectuer des recherches sans réanalyser toute l'arborescence
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Catalog {
+    version: u32,
+    root: PathBuf,
+    #[serde(deserialize_with = "deserialize_files")]
+    files: Vec<MediaFile>,
+    // Index inversé des fichiers, sauvegardé dans un fichier séparé (voir index::index_path)
+    #[serde(skip)]
+    index: Index,
+}
+
+
+impl Catalog {
+    // Construit un catalogue à partir du répertoire scanné et des fichiers trouvés
+    pub fn new(root: &Path, files: Vec<MediaFile>) -> Catalog {
+        Catalog {
+            version: CATALOG_VERSION,
+            root: root.to_path_buf(),
+            index: Index::build(root, &files),
+            files,
+        }
+    }
+
+    // Construit un catalogue en analysant le répertoire 'root' avec 'workers' threads de lecture.
+    // Renvoie également le bilan de l'analyse, qui liste les fichiers ignorés
+    pub fn scan(root: &Path, workers: usize) -> (Catalog, ScanReport) {
+        let (files, report) = rescan(root, &[], workers);
+        (Catalog::new(root, files), report)
+    }
+
+    // Répertoire racine à partir duquel le catalogue a été construit
+    pub fn root(&self) -> &Path {
+        self.root.as_path()
+    }
+
+    // Fichiers contenus dans le catalogue
+    pub fn files(&self) -> &[MediaFile] {
+        &self.files
+    }
+
+    // Index inversé des fichiers du catalogue
+    pub fn index(&self) -> &Index {
+        &self.index
+    }
+
+    // Nombre de fichiers contenus dans le catalogue
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    // Indique si le catalogue est vide
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    // Met à jour le catalogue en réanalysant son répertoire racine : seuls les fichiers
+    // ajoutés ou modifiés depuis la dernière analyse sont relus, avec 'workers' threads de lecture
+    pub fn rescan(&mut self, workers: usize) -> ScanReport {
+        let (files, report) = rescan(&self.root, &self.files, workers);
+        self.files = files;
+        self.index = Index::build(&self.root, &self.files);
+        report
+    }
+
+    // Remplace les médias du catalogue ayant le même chemin que les médias modifiés 'medias'
+    // puis reconstruit l'index
+    pub fn update(&mut self, medias: Vec<MediaFile>) {
+        if medias.is_empty() {
+            return;
+        }
+        let mut updated: HashMap<PathBuf, MediaFile> = medias.into_iter()
+            .map(|media| (media.path().to_path_buf(), media))
+            .collect();
+        for media in self.files.iter_mut() {
+            if let Some(new) = updated.remove(media.path()) {
+                *media = new;
+            }
+        }
+        self.index = Index::build(&self.root, &self.files);
+    }
+
+    // Sauvegarde le catalogue au format JSON dans le fichier 'path',
+    // et son index à côté (catalog.json -> catalog.index.json)
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(writer, self)?;
+        self.index.save(&index_path(path))
+    }
+
+    // Charge un catalogue depuis le fichier JSON 'path'.
+    // Renvoie une erreur si le fichier est illisible ou si sa version n'est pas supportée.
+    // Les fichiers d'un catalogue d'une version précédente sont marqués comme modifiés afin que la
+    // prochaine réanalyse relise leurs tags d'origine et les propriétés de leur flux audio
+    pub fn load(path: &Path) -> io::Result<Catalog> {
+        let reader = BufReader::new(File::open(path)?);
+        let mut catalog: Catalog = serde_json::from_reader(reader)?;
+        if catalog.version < CATALOG_VERSION {
+            for music_file in catalog.files.iter_mut().filter_map(MediaFile::as_audio_mut) {
+                music_file.modified = UNIX_EPOCH;
+            }
+            catalog.version = CATALOG_VERSION;
+        }
+        if catalog.version != CATALOG_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Version de catalogue non supportée : {} (version attendue : {})", catalog.version, CATALOG_VERSION),
+            ));
+        }
+        // Chargement de l'index sauvegardé, ou reconstruction s'il est absent ou périmé
+        catalog.index = match Index::load(&index_path(path)) {
+            Ok(index) if index.is_up_to_date(&catalog.root, &catalog.files) => index,
+            _ => Index::build(&catalog.root, &catalog.files),
+        };
+        Ok(catalog)
+    }
+}
+
+
+// Média tel qu'il est enregistré dans un catalogue : avec son type, ou sans type pour un catalogue
+// antérieur à la version 4, qui ne contient que des fichiers audio
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredMedia {
+    Typed(MediaFile),
+    Audio(MusicFile),
+}
+
+
+// Lit les médias d'un catalogue, quelle que soit sa version
+fn deserialize_files<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<MediaFile>, D::Error> {
+    let files: Vec<StoredMedia> = Vec::deserialize(deserializer)?;
+    Ok(files.into_iter()
+        .map(|media| match media {
+            StoredMedia::Typed(media) => media,
+            StoredMedia::Audio(music_file) => MediaFile::Audio(music_file),
+        })
+        .collect())
+}
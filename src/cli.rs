@@ -1,4 +1,5 @@
 use structopt::StructOpt;
+use crate::scan::default_workers;
 
 /// Représente les arguments en paramètres de ligne de commande
 #[derive(Debug)]
@@ -7,13 +8,31 @@ pub struct CliArguments {
     /// Commande à exécuter
     command: String,
 
-    /// Chemin où trouver les fichiers à analyser
+    /// Chemin où trouver les fichiers à analyser (ou catalogue JSON sauvegardé)
     #[structopt(parse(from_os_str))]
     path: std::path::PathBuf,
 
-    /// Le reste des arguments (à passer aux commandes search et write2md).
+    /// Le reste des arguments (à passer aux commandes search, write2md, stats, tag et save).
     /// Pour plus d'info sur le mode d'emploi, exécutez le programme sans argument
     arguments: Option<String>,
+
+    /// Affiche les résultats de la commande search au format JSON (avec leur score)
+    #[structopt(long)]
+    json: bool,
+
+    /// Affiche les modifications de la commande tag sans modifier les fichiers
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Conserve les mots-clés modifiés par la commande tag dans le catalogue seulement, sans modifier les fichiers
+    #[structopt(long)]
+    catalog_only: bool,
+
+    /// Nombre de threads de lecture des fichiers lors d'une analyse (par défaut, le nombre de cœurs disponibles).
+    /// Les fichiers sont lus en parallèle pendant le parcours des répertoires ; le bilan de l'analyse
+    /// indique le nombre de fichiers lus et le débit de lecture
+    #[structopt(short = "j", long = "jobs")]
+    jobs: Option<usize>,
 }
 
 impl CliArguments {
@@ -35,4 +54,30 @@ impl CliArguments {
         self.arguments.clone()
     }
 
+    pub fn is_json(&self) -> bool
+    {
+        self.json
+    }
+
+    pub fn is_dry_run(&self) -> bool
+    {
+        self.dry_run
+    }
+
+    pub fn is_catalog_only(&self) -> bool
+    {
+        self.catalog_only
+    }
+
+    pub fn get_workers(&self) -> usize
+    {
+        self.jobs.unwrap_or_else(default_workers).max(1)
+    }
+
+}
+
+impl Default for CliArguments {
+    fn default() -> Self {
+        Self::new()
+    }
 }
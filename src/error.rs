@@ -0,0 +1,60 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+
+// Erreurs pouvant survenir lors de l'analyse d'un répertoire ou de la modification des tags :
+// elles concernent un fichier (ou un répertoire) particulier, qui est alors ignoré sans interrompre le traitement
+#[derive(Debug)]
+pub enum MedmanError {
+    // Erreur lors du parcours d'un répertoire (droits insuffisants, lien symbolique cassé...)
+    Walk(walkdir::Error),
+    // Erreur d'entrée/sortie lors de l'accès à un fichier
+    Io(PathBuf, io::Error),
+    // Fichier dont le format ou les métadonnées sont incorrects
+    InvalidMedia(PathBuf, String),
+    // Erreur lors de l'écriture des tags d'un fichier
+    TagWrite(PathBuf, String),
+    // Chemin qui n'est pas de l'UTF-8 valide (il ne pourrait pas être sauvegardé dans le catalogue)
+    InvalidPath(PathBuf),
+}
+
+
+impl MedmanError {
+    // Chemin du fichier ou du répertoire concerné par l'erreur, s'il est connu
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            MedmanError::Walk(e) => e.path(),
+            MedmanError::Io(path, _) | MedmanError::InvalidMedia(path, _) | MedmanError::TagWrite(path, _)
+                | MedmanError::InvalidPath(path) => Some(path),
+        }
+    }
+}
+
+
+impl fmt::Display for MedmanError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MedmanError::Walk(e) => match e.path() {
+                Some(path) => write!(f, "{:?} : parcours impossible ({})", path, e),
+                None => write!(f, "parcours impossible ({})", e),
+            },
+            MedmanError::Io(path, e) => write!(f, "{:?} : lecture impossible ({})", path, e),
+            MedmanError::InvalidMedia(path, reason) => write!(f, "{:?} : fichier incorrect ({})", path, reason),
+            MedmanError::TagWrite(path, reason) => write!(f, "{:?} : écriture des tags impossible ({})", path, reason),
+            MedmanError::InvalidPath(path) => write!(f, "{:?} : chemin non UTF-8", path),
+        }
+    }
+}
+
+
+impl Error for MedmanError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            MedmanError::Walk(e) => Some(e),
+            MedmanError::Io(_, e) => Some(e),
+            _ => None,
+        }
+    }
+}
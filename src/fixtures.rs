@@ -0,0 +1,35 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+
+// Fichier temporaire contenant un fichier de test construit en mémoire, supprimé en fin de test.
+// Son nom comporte le numéro du processus, les tests étant exécutés en parallèle
+pub(crate) struct TemporaryFile {
+    path: PathBuf,
+}
+
+
+impl TemporaryFile {
+    pub(crate) fn new(name: &str, bytes: &[u8]) -> TemporaryFile {
+        let path = env::temp_dir().join(format!("medman-{}-{}", process::id(), name));
+        fs::write(&path, bytes).unwrap();
+        TemporaryFile { path }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn bytes(&self) -> Vec<u8> {
+        fs::read(&self.path).unwrap()
+    }
+}
+
+
+impl Drop for TemporaryFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,327 @@
+use std::convert::TryInto;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::Duration;
+use crate::error::MedmanError;
+use crate::musicfile::MusicFile;
+use crate::vorbis::VorbisComments;
+
+
+// Types des blocs de métadonnées FLAC utilisés
+const STREAMINFO: u8 = 0;
+const PADDING: u8 = 1;
+const VORBIS_COMMENT: u8 = 4;
+
+// Taille du bloc de remplissage ajouté lorsque les métadonnées ne tiennent plus dans leur emplacement
+const DEFAULT_PADDING: u64 = 4096;
+
+// Taille maximale d'un bloc de métadonnées (longueur sur 24 bits)
+const MAX_BLOCK_LENGTH: u64 = 0xff_ffff;
+
+
+// Métadonnées d'un fichier FLAC : position de la signature "fLaC" (après un éventuel tag ID3v2),
+// blocs lus (type et contenu) et position du début des données audio
+struct FlacMetadata {
+    start: u64,
+    blocks: Vec<(u8, Vec<u8>)>,
+    audio_start: u64,
+}
+
+
+// Complète le média avec les métadonnées d'un fichier FLAC : durée, fréquence d'échantillonnage et
+// résolution lues dans le bloc STREAMINFO, tags lus dans le bloc de commentaires Vorbis
+// (un bloc de commentaires incorrect est ignoré)
+pub(crate) fn read_flac_metadata(music_file: &mut MusicFile) -> Result<(), MedmanError> {
+    let path = music_file.path.clone();
+    let file = File::open(&path).map_err(|e| MedmanError::Io(path.clone(), e))?;
+    let metadata = read_blocks(&mut BufReader::new(file), &path, |block_type| block_type == STREAMINFO || block_type == VORBIS_COMMENT)?;
+
+    let mut has_streaminfo = false;
+    for (block_type, data) in &metadata.blocks {
+        if *block_type == STREAMINFO && data.len() >= 18 {
+            fill_streaminfo(music_file, data);
+            has_streaminfo = true;
+        } else if let Some(comments) = VorbisComments::parse(data).filter(|_| *block_type == VORBIS_COMMENT) {
+            comments.fill(music_file);
+        }
+    }
+    if !has_streaminfo {
+        return Err(MedmanError::InvalidMedia(path, "bloc STREAMINFO absent".to_string()));
+    }
+    Ok(())
+}
+
+
+// Lit les blocs de métadonnées d'un fichier FLAC. Seul le contenu des blocs dont le type satisfait
+// 'keep' est conservé, les autres sont sautés
+fn read_blocks<R: Read + Seek>(reader: &mut R, path: &Path, keep: impl Fn(u8) -> bool) -> Result<FlacMetadata, MedmanError> {
+    let error = |e: io::Error| match e.kind() {
+        io::ErrorKind::UnexpectedEof => MedmanError::InvalidMedia(path.to_path_buf(), "fichier FLAC tronqué".to_string()),
+        _ => MedmanError::Io(path.to_path_buf(), e),
+    };
+    let mut start = 0;
+    let mut signature = [0; 4];
+    reader.read_exact(&mut signature).map_err(error)?;
+    if &signature[..3] == b"ID3" {
+        // En-tête ID3v2 : 10 octets, dont la taille du tag sur 4 octets de 7 bits (synchsafe)
+        let mut header = [0; 6];
+        reader.read_exact(&mut header).map_err(error)?;
+        let size = header[2..].iter().fold(0u64, |size, byte| size << 7 | (*byte & 0x7f) as u64);
+        start = 10 + size;
+        reader.seek(SeekFrom::Start(start)).map_err(error)?;
+        reader.read_exact(&mut signature).map_err(error)?;
+    }
+    if &signature != b"fLaC" {
+        return Err(MedmanError::InvalidMedia(path.to_path_buf(), "signature FLAC absente".to_string()));
+    }
+
+    let mut blocks = Vec::new();
+    let mut position = start + 4;
+    loop {
+        // En-tête de bloc : indicateur de dernier bloc, type sur 7 bits et longueur sur 24 bits
+        let mut header = [0; 4];
+        reader.read_exact(&mut header).map_err(error)?;
+        let block_type = header[0] & 0x7f;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+        if keep(block_type) {
+            let mut data = vec![0; length as usize];
+            reader.read_exact(&mut data).map_err(error)?;
+            blocks.push((block_type, data));
+        } else {
+            reader.seek(SeekFrom::Current(length as i64)).map_err(error)?;
+        }
+        position += 4 + length;
+        if header[0] & 0x80 != 0 {
+            break;
+        }
+    }
+    Ok(FlacMetadata { start, blocks, audio_start: position })
+}
+
+
+// Lit le bloc STREAMINFO : les octets 10 à 17 contiennent la fréquence d'échantillonnage (20 bits),
+// le nombre de canaux (3 bits), la résolution (5 bits) et le nombre total d'échantillons (36 bits)
+fn fill_streaminfo(music_file: &mut MusicFile, data: &[u8]) {
+    let bits = u64::from_be_bytes(data[10..18].try_into().unwrap());
+    let sample_rate = (bits >> 44) as u32;
+    let total_samples = bits & 0xf_ffff_ffff;
+    music_file.sample_rate = sample_rate;
+    music_file.bit_depth = ((bits >> 36 & 0x1f) + 1) as u8;
+    music_file.channels = ((bits >> 41 & 0x7) + 1) as u8;
+    // Le nombre total d'échantillons est nul s'il est inconnu
+    if sample_rate != 0 {
+        let rate = sample_rate as u64;
+        music_file.duration = Duration::from_secs(total_samples / rate)
+            + Duration::from_nanos(total_samples % rate * 1_000_000_000 / rate);
+    }
+}
+
+
+// Écrit les champs du média dans le bloc de commentaires Vorbis du fichier FLAC (créé s'il n'existe pas).
+// Les commentaires dont la valeur ne change pas, les autres commentaires et les autres blocs sont conservés.
+// Les métadonnées sont réécrites sur place si elles tiennent dans leur emplacement (en réduisant le bloc
+// de remplissage), sinon le fichier est recopié dans un fichier temporaire qui remplace l'original
+pub(crate) fn write_flac_tags(music_file: &MusicFile) -> Result<(), MedmanError> {
+    let path = music_file.path.as_path();
+    let io_error = |e: io::Error| MedmanError::Io(path.to_path_buf(), e);
+    let write_error = |reason: &str| MedmanError::TagWrite(path.to_path_buf(), reason.to_string());
+
+    let mut file = BufReader::new(File::open(path).map_err(io_error)?);
+    let FlacMetadata { start, mut blocks, audio_start } = read_blocks(&mut file, path, |block_type| block_type != PADDING)?;
+    let position = blocks.iter().position(|(block_type, _)| *block_type == VORBIS_COMMENT);
+    let mut comments = match position {
+        Some(position) => VorbisComments::parse(&blocks[position].1).ok_or_else(|| write_error("commentaires Vorbis incorrects"))?,
+        None => VorbisComments { vendor: "medman".to_string(), comments: Vec::new() },
+    };
+    if !comments.update(music_file) {
+        return Ok(());
+    }
+    let data = comments.to_bytes();
+    if data.len() as u64 > MAX_BLOCK_LENGTH {
+        return Err(write_error("commentaires Vorbis trop longs"));
+    }
+    match position {
+        Some(position) => blocks[position].1 = data,
+        // Le bloc STREAMINFO doit rester le premier
+        None => blocks.insert(1.min(blocks.len()), (VORBIS_COMMENT, data)),
+    }
+
+    // Le bloc de remplissage occupe la place libérée si les blocs tiennent dans l'emplacement d'origine
+    let old_length = audio_start - start;
+    let length = 4 + blocks.iter().map(|(_, data)| 4 + data.len() as u64).sum::<u64>();
+    let in_place = length + 4 <= old_length && old_length - length - 4 <= MAX_BLOCK_LENGTH;
+    let padding = if in_place { old_length - length - 4 } else { DEFAULT_PADDING };
+    blocks.push((PADDING, vec![0; padding as usize]));
+    let mut header = b"fLaC".to_vec();
+    for (i, (block_type, data)) in blocks.iter().enumerate() {
+        let last = if i == blocks.len() - 1 { 0x80 } else { 0 };
+        header.push(block_type | last);
+        header.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+        header.extend_from_slice(data);
+    }
+
+    if in_place {
+        drop(file);
+        let mut file = OpenOptions::new().write(true).open(path).map_err(io_error)?;
+        file.seek(SeekFrom::Start(start)).map_err(io_error)?;
+        return file.write_all(&header).map_err(io_error);
+    }
+    let temporary = path.with_extension("flac.medman");
+    copy_with_header(&mut file, &temporary, start, &header, audio_start)
+        .and_then(|_| fs::set_permissions(&temporary, fs::metadata(path)?.permissions()))
+        .and_then(|_| fs::rename(&temporary, path))
+        .map_err(|e| {
+            let _ = fs::remove_file(&temporary);
+            io_error(e)
+        })
+}
+
+
+// Recopie le fichier 'file' dans 'destination' en remplaçant les octets de 'start' à 'audio_start'
+// (les métadonnées FLAC) par 'header'
+fn copy_with_header<R: Read + Seek>(file: &mut R, destination: &Path, start: u64, header: &[u8], audio_start: u64) -> io::Result<()> {
+    let mut output = BufWriter::new(File::create(destination)?);
+    file.seek(SeekFrom::Start(0))?;
+    io::copy(&mut file.by_ref().take(start), &mut output)?;
+    output.write_all(header)?;
+    file.seek(SeekFrom::Start(audio_start))?;
+    io::copy(file, &mut output)?;
+    output.flush()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use crate::fixtures::TemporaryFile;
+    use super::*;
+
+    const AUDIO: &[u8] = b"trames audio";
+
+    // Bloc STREAMINFO : 2 canaux de 16 bits, somme MD5 nulle
+    fn streaminfo(sample_rate: u64, total_samples: u64) -> Vec<u8> {
+        let mut data = vec![0; 10];
+        data.extend_from_slice(&(sample_rate << 44 | 1 << 41 | 15 << 36 | total_samples).to_be_bytes());
+        data.resize(34, 0);
+        data
+    }
+
+    fn comments(comments: &[&str]) -> Vec<u8> {
+        VorbisComments { vendor: "test".to_string(), comments: comments.iter().map(|c| c.to_string()).collect() }.to_bytes()
+    }
+
+    // Fichier FLAC formé de la signature, des blocs et des données audio
+    fn flac(blocks: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        let mut bytes = b"fLaC".to_vec();
+        for (i, (block_type, data)) in blocks.iter().enumerate() {
+            bytes.push(block_type | if i == blocks.len() - 1 { 0x80 } else { 0 });
+            bytes.extend_from_slice(&(data.len() as u32).to_be_bytes()[1..]);
+            bytes.extend_from_slice(data);
+        }
+        bytes.extend_from_slice(AUDIO);
+        bytes
+    }
+
+    fn read(path: &Path) -> MusicFile {
+        let mut music_file = MusicFile::new(path);
+        read_flac_metadata(&mut music_file).unwrap();
+        music_file
+    }
+
+    // Types et longueurs des blocs du fichier
+    fn block_layout(bytes: &[u8]) -> Vec<(u8, usize)> {
+        let metadata = read_blocks(&mut Cursor::new(bytes), Path::new("test.flac"), |_| true).unwrap();
+        metadata.blocks.iter().map(|(block_type, data)| (*block_type, data.len())).collect()
+    }
+
+    #[test]
+    fn reads_streaminfo_and_comments() {
+        let file = TemporaryFile::new("read.flac", &flac(&[
+            (STREAMINFO, streaminfo(44100, 110250)),
+            (VORBIS_COMMENT, comments(&["TITLE=So What", "artist=Miles Davis", "DATE=1959-03-02", "TRACKNUMBER=1/5"])),
+        ]));
+        let music_file = read(file.path());
+        assert_eq!((music_file.sample_rate, music_file.channels, music_file.bit_depth), (44100, 2, 16));
+        assert_eq!(music_file.duration, Duration::from_millis(2500));
+        assert_eq!((music_file.title.as_str(), music_file.author.as_str()), ("So What", "Miles Davis"));
+        assert_eq!((music_file.year, music_file.track), (1959, 1));
+    }
+
+    #[test]
+    fn rewrites_in_place_when_padding_is_large_enough() {
+        let file = TemporaryFile::new("in_place.flac", &flac(&[
+            (STREAMINFO, streaminfo(44100, 88200)),
+            (VORBIS_COMMENT, comments(&["TITLE=Old", "REPLAYGAIN_TRACK_GAIN=-3 dB"])),
+            (PADDING, vec![0; 1024]),
+        ]));
+        let length = file.bytes().len();
+        let mut music_file = read(file.path());
+        music_file.title = "A much longer title".to_string();
+        music_file.keywords = vec!["live".to_string()];
+        write_flac_tags(&music_file).unwrap();
+
+        let bytes = file.bytes();
+        assert_eq!(bytes.len(), length);
+        assert!(bytes.ends_with(AUDIO));
+        let layout = block_layout(&bytes);
+        assert_eq!(layout.iter().map(|(block_type, _)| *block_type).collect::<Vec<_>>(), [STREAMINFO, VORBIS_COMMENT, PADDING]);
+        assert!(layout[2].1 < 1024);
+        let written = read(file.path());
+        assert_eq!(written.title, "A much longer title");
+        assert_eq!(written.keywords, ["live"]);
+        assert_eq!(written.duration, Duration::from_secs(2));
+        // Les commentaires non gérés sont conservés
+        let metadata = read_blocks(&mut Cursor::new(&bytes), file.path(), |block_type| block_type == VORBIS_COMMENT).unwrap();
+        let comments = VorbisComments::parse(&metadata.blocks[0].1).unwrap();
+        assert!(comments.comments.contains(&"REPLAYGAIN_TRACK_GAIN=-3 dB".to_string()));
+    }
+
+    #[test]
+    fn rewrites_through_a_temporary_file_when_padding_is_too_small() {
+        let file = TemporaryFile::new("copy.flac", &flac(&[
+            (STREAMINFO, streaminfo(48000, 48000)),
+            (PADDING, vec![0; 8]),
+        ]));
+        let mut music_file = read(file.path());
+        music_file.comment = "x".repeat(200);
+        write_flac_tags(&music_file).unwrap();
+
+        let bytes = file.bytes();
+        assert!(bytes.ends_with(AUDIO));
+        let layout = block_layout(&bytes);
+        assert_eq!(layout[0], (STREAMINFO, 34));
+        assert_eq!(layout[1].0, VORBIS_COMMENT);
+        assert_eq!(layout[2], (PADDING, DEFAULT_PADDING as usize));
+        assert!(!file.path().with_extension("flac.medman").exists());
+        let written = read(file.path());
+        assert_eq!(written.comment, "x".repeat(200));
+        assert_eq!(written.duration, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn unchanged_tags_leave_the_file_untouched() {
+        let bytes = flac(&[(STREAMINFO, streaminfo(44100, 44100)), (VORBIS_COMMENT, comments(&["TITLE=Same"]))]);
+        let file = TemporaryFile::new("unchanged.flac", &bytes);
+        write_flac_tags(&read(file.path())).unwrap();
+        assert_eq!(file.bytes(), bytes);
+    }
+
+    #[test]
+    fn truncated_streaminfo_is_an_error() {
+        let mut bytes = flac(&[(STREAMINFO, streaminfo(44100, 44100))]);
+        bytes.truncate(4 + 4 + 10);
+        let file = TemporaryFile::new("truncated.flac", &bytes);
+        let mut music_file = MusicFile::new(file.path());
+        assert!(matches!(read_flac_metadata(&mut music_file), Err(MedmanError::InvalidMedia(..))));
+        music_file.title = "Title".to_string();
+        assert!(write_flac_tags(&music_file).is_err());
+        assert_eq!(file.bytes(), bytes);
+
+        // Bloc complet mais trop court pour contenir les caractéristiques du flux
+        let file = TemporaryFile::new("short.flac", &flac(&[(STREAMINFO, vec![0; 10])]));
+        assert!(matches!(read_flac_metadata(&mut MusicFile::new(file.path())), Err(MedmanError::InvalidMedia(..))));
+        let file = TemporaryFile::new("signature.flac", b"fLa");
+        assert!(matches!(read_flac_metadata(&mut MusicFile::new(file.path())), Err(MedmanError::InvalidMedia(..))));
+    }
+}
@@ -0,0 +1,347 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use serde::{Serialize, Deserialize};
+use crate::media::{search_fields, FieldValue, Media, MediaFile};
+use crate::query::{Matcher, Query};
+use crate::search::{free_text_matches, free_text_value, normalize, numeric_value, term_matches, text_matches, text_values, SearchField};
+
+
+// Version courante du format de fichier de l'index
+// Version 6 : indexation du champ kind (type de média)
+// Version 7 : indexation des champs des photos (appareil, date de prise de vue, dimensions, coordonnées GPS)
+// Version 8 : indexation des champs des vidéos (dimensions, nombre d'images par seconde, codecs)
+// Version 9 : indexation des propriétés du flux audio (débit, fréquence, canaux, version MPEG)
+// Version 10 : l'année et les numéros de piste et de disque inconnus ne sont plus indexés
+// Version 11 : les dimensions et le nombre d'images par seconde inconnus des vidéos ne sont plus indexés
+pub const INDEX_VERSION: u32 = 11;
+
+
+// Liste triée et sans doublon de numéros de documents (positions des médias dans le catalogue)
+pub type DocSet = Vec<u32>;
+
+
+// Index inversé d'un catalogue, permettant de répondre aux requêtes sans parcourir tous les médias.
+// Les champs sont désignés par leur nom (voir SearchField::name)
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Index {
+    version: u32,
+    // Empreinte du catalogue indexé, pour détecter un index périmé
+    fingerprint: u64,
+    // Nombre de documents indexés
+    doc_count: u32,
+    // Pour chaque champ texte : valeur normalisée complète -> documents
+    values: HashMap<String, BTreeMap<String, DocSet>>,
+    // Pour chaque champ texte : mot normalisé -> documents (postings des termes libres)
+    terms: HashMap<String, BTreeMap<String, DocSet>>,
+    // Pour chaque champ texte : nombre total de mots, pour la longueur moyenne du champ
+    lengths: HashMap<String, u64>,
+    // Pour chaque champ numérique : couples (valeur, document) triés par valeur
+    numbers: HashMap<String, Vec<(u64, u32)>>,
+}
+
+
+impl Index {
+    // Construit l'index des médias 'files' scannés à partir du répertoire 'root'
+    pub fn build(root: &Path, files: &[MediaFile]) -> Index {
+        let mut index = Index {
+            version: INDEX_VERSION,
+            fingerprint: fingerprint(root, files),
+            doc_count: files.len() as u32,
+            ..Index::default()
+        };
+        for (doc, media) in files.iter().enumerate() {
+            let doc = doc as u32;
+            for field in search_fields().iter().filter(|field| !field.is_numeric()) {
+                for text in text_values(field, media, root) {
+                    add_posting(index.values.entry(field.name().to_string()).or_default(), normalize(&text), doc);
+                }
+                let terms = tokenize(&normalize(&free_text_value(field, media, root)));
+                *index.lengths.entry(field.name().to_string()).or_default() += terms.len() as u64;
+                for term in terms {
+                    add_posting(index.terms.entry(field.name().to_string()).or_default(), term, doc);
+                }
+            }
+            for field in search_fields().iter().filter(|field| field.is_numeric()) {
+                if let Some(value) = numeric_value(field, media) {
+                    index.numbers.entry(field.name().to_string()).or_default().push((value, doc));
+                }
+            }
+        }
+        for column in index.numbers.values_mut() {
+            column.sort_unstable();
+        }
+        index
+    }
+
+    // Indique si l'index correspond aux médias 'files' scannés à partir de 'root'
+    pub fn is_up_to_date(&self, root: &Path, files: &[MediaFile]) -> bool {
+        self.version == INDEX_VERSION && self.fingerprint == fingerprint(root, files)
+    }
+
+    // Nombre de documents indexés
+    pub fn doc_count(&self) -> u32 {
+        self.doc_count
+    }
+
+    // Postings d'un mot dans un champ texte
+    pub fn postings(&self, field: &SearchField, term: &str) -> Option<&DocSet> {
+        self.terms.get(field.name()).and_then(|terms| terms.get(term))
+    }
+
+    // Nombre de documents dont le champ texte contient le mot 'term'
+    pub fn document_frequency(&self, field: &SearchField, term: &str) -> usize {
+        self.postings(field, term).map_or(0, |docs| docs.len())
+    }
+
+    // Nombre de documents dont le champ texte a pour valeur normalisée 'value'
+    pub fn value_frequency(&self, field: &SearchField, value: &str) -> usize {
+        self.values.get(field.name()).and_then(|values| values.get(value)).map_or(0, |docs| docs.len())
+    }
+
+    // Nombre moyen de mots du champ texte
+    pub fn average_length(&self, field: &SearchField) -> f32 {
+        match (self.lengths.get(field.name()), self.doc_count) {
+            (Some(total), count) if count > 0 => *total as f32 / count as f32,
+            _ => 0.0,
+        }
+    }
+
+    // Renvoie les numéros des documents satisfaisant la requête, dans l'ordre du catalogue
+    pub fn evaluate(&self, query: &Query, files: &[MediaFile], root: &Path) -> DocSet {
+        match query {
+            Query::Term(field, matcher) => self.evaluate_term(field, matcher, files, root),
+            Query::FreeText(matcher) => search_fields().iter()
+                .filter(|field| field.weight() > 0)
+                .fold(DocSet::new(), |result, field| union(&result, &self.evaluate_free_text(field, matcher, files, root))),
+            Query::And(operands) => {
+                let mut operands = operands.iter();
+                let mut result = match operands.next() {
+                    Some(operand) => self.evaluate(operand, files, root),
+                    None => return self.all(),
+                };
+                for operand in operands {
+                    if result.is_empty() {
+                        break;
+                    }
+                    result = intersection(&result, &self.evaluate(operand, files, root));
+                }
+                result
+            },
+            Query::Or(operands) => operands.iter()
+                .fold(DocSet::new(), |result, operand| union(&result, &self.evaluate(operand, files, root))),
+            Query::Not(operand) => difference(&self.all(), &self.evaluate(operand, files, root)),
+            Query::Boost(operand, _) => self.evaluate(operand, files, root),
+        }
+    }
+
+    // Renvoie les documents dont le champ satisfait la condition : les valeurs et les colonnes
+    // numériques de l'index sont utilisées lorsque c'est possible, sinon chaque média est évalué
+    fn evaluate_term(&self, field: &SearchField, matcher: &Matcher, files: &[MediaFile], root: &Path) -> DocSet {
+        let values = self.values.get(field.name());
+        match (matcher, values) {
+            (Matcher::Text(value), Some(values)) => values.get(value).cloned().unwrap_or_default(),
+            // L'égalité stricte implique l'égalité des valeurs normalisées : les documents
+            // de la valeur normalisée sont des candidats à vérifier
+            (Matcher::Exact(value), Some(values)) => values.get(&normalize(value))
+                .map(|docs| self.filter(docs, field, matcher, files, root))
+                .unwrap_or_default(),
+            // Les valeurs de l'index étant normalisées, elles sont directement comparées au motif
+            (Matcher::Wildcard(_, false), Some(values))
+            | (Matcher::Fuzzy(_, _, false), Some(values))
+            | (Matcher::Regex(_, false), Some(values)) => {
+                let mut result: DocSet = values.iter()
+                    .filter(|(value, _)| text_matches(matcher, value))
+                    .flat_map(|(_, docs)| docs.iter().copied())
+                    .collect();
+                result.sort_unstable();
+                result.dedup();
+                result
+            },
+            (Matcher::Range(min, max), _) => match self.numbers.get(field.name()) {
+                Some(column) => {
+                    let start = column.partition_point(|(value, _)| value < min);
+                    let end = max.map_or(column.len(), |max| column.partition_point(|(value, _)| *value < max));
+                    let mut result: DocSet = column[start..end.max(start)].iter().map(|(_, doc)| *doc).collect();
+                    result.sort_unstable();
+                    result
+                },
+                None => DocSet::new(),
+            },
+            _ => self.filter(&self.all(), field, matcher, files, root),
+        }
+    }
+
+    // Renvoie les documents dont un mot du champ satisfait la condition du terme libre
+    fn evaluate_free_text(&self, field: &SearchField, matcher: &Matcher, files: &[MediaFile], root: &Path) -> DocSet {
+        let terms = match self.terms.get(field.name()) {
+            Some(terms) => terms,
+            None => return DocSet::new(),
+        };
+        match matcher {
+            Matcher::Text(phrase) => {
+                let words = tokenize(phrase);
+                let mut result = match words.first().and_then(|word| terms.get(word)) {
+                    Some(docs) => docs.clone(),
+                    None => return DocSet::new(),
+                };
+                for word in words.iter().skip(1) {
+                    result = intersection(&result, terms.get(word).map_or(&[][..], |docs| docs.as_slice()));
+                }
+                // Les documents contenant tous les mots d'une phrase sont vérifiés pour l'ordre des mots
+                if words.len() > 1 {
+                    result.retain(|doc| free_text_matches(field, matcher, &files[*doc as usize], root));
+                }
+                result
+            },
+            _ => {
+                let mut result: DocSet = terms.iter()
+                    .filter(|(term, _)| text_matches(matcher, term))
+                    .flat_map(|(_, docs)| docs.iter().copied())
+                    .collect();
+                result.sort_unstable();
+                result.dedup();
+                result
+            },
+        }
+    }
+
+    // Conserve parmi les documents 'docs' ceux dont le champ satisfait la condition
+    fn filter(&self, docs: &[u32], field: &SearchField, matcher: &Matcher, files: &[MediaFile], root: &Path) -> DocSet {
+        docs.iter()
+            .copied()
+            .filter(|doc| files.get(*doc as usize).is_some_and(|media| term_matches(field, matcher, media, root)))
+            .collect()
+    }
+
+    // Ensemble de tous les documents
+    fn all(&self) -> DocSet {
+        (0..self.doc_count).collect()
+    }
+
+    // Sauvegarde l'index au format JSON dans le fichier 'path'
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        let writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+
+    // Charge un index depuis le fichier JSON 'path'
+    pub fn load(path: &Path) -> io::Result<Index> {
+        let reader = BufReader::new(File::open(path)?);
+        Ok(serde_json::from_reader(reader)?)
+    }
+}
+
+
+// Chemin du fichier d'index associé au catalogue 'catalog_path' (catalog.json -> catalog.index.json)
+pub fn index_path(catalog_path: &Path) -> PathBuf {
+    let stem = catalog_path.file_stem().unwrap_or_default().to_string_lossy();
+    catalog_path.with_file_name(format!("{}.index.json", stem))
+}
+
+
+// Découpe un texte normalisé en mots (suites de caractères alphanumériques)
+pub fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|term| !term.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+
+// Ajoute le document 'doc' aux postings de 'key'. Les documents étant ajoutés
+// dans l'ordre croissant, il suffit de comparer au dernier pour éviter les doublons
+fn add_posting(postings: &mut BTreeMap<String, DocSet>, key: String, doc: u32) {
+    let docs = postings.entry(key).or_default();
+    if docs.last() != Some(&doc) {
+        docs.push(doc);
+    }
+}
+
+
+// Empreinte (FNV-1a) du répertoire racine et des types, chemins, tailles et dates de modification
+// des médias, ainsi que de leurs mots-clés (qui peuvent être modifiés sans toucher aux fichiers) :
+// elle change dès que le contenu du catalogue change
+fn fingerprint(root: &Path, files: &[MediaFile]) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    let mut feed = |bytes: &[u8]| {
+        for byte in bytes {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    };
+    feed(root.to_string_lossy().as_bytes());
+    for media in files {
+        feed(media.kind().as_bytes());
+        feed(media.path().to_string_lossy().as_bytes());
+        feed(&media.size().to_le_bytes());
+        let modified = media.modified().duration_since(UNIX_EPOCH).unwrap_or_default();
+        feed(&modified.as_nanos().to_le_bytes());
+        if let Some(FieldValue::Text(keywords)) = media.field("keyword") {
+            for keyword in keywords {
+                feed(keyword.as_bytes());
+                feed(&[0]);
+            }
+        }
+    }
+    hash
+}
+
+
+// Intersection de deux ensembles de documents triés
+pub fn intersection(a: &[u32], b: &[u32]) -> DocSet {
+    let mut result = DocSet::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() && j < b.len() {
+        if a[i] < b[j] {
+            i += 1;
+        } else if a[i] > b[j] {
+            j += 1;
+        } else {
+            result.push(a[i]);
+            i += 1;
+            j += 1;
+        }
+    }
+    result
+}
+
+
+// Union de deux ensembles de documents triés
+pub fn union(a: &[u32], b: &[u32]) -> DocSet {
+    let mut result = DocSet::with_capacity(a.len() + b.len());
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if j == b.len() || (i < a.len() && a[i] < b[j]) {
+            result.push(a[i]);
+            i += 1;
+        } else if i == a.len() || b[j] < a[i] {
+            result.push(b[j]);
+            j += 1;
+        } else {
+            result.push(a[i]);
+            i += 1;
+            j += 1;
+        }
+    }
+    result
+}
+
+
+// Différence de deux ensembles de documents triés (documents de 'a' absents de 'b')
+pub fn difference(a: &[u32], b: &[u32]) -> DocSet {
+    let mut result = DocSet::new();
+    let mut j = 0;
+    for doc in a {
+        while j < b.len() && b[j] < *doc {
+            j += 1;
+        }
+        if j == b.len() || b[j] != *doc {
+            result.push(*doc);
+        }
+    }
+    result
+}
@@ -1,4 +1,22 @@
+pub mod catalog;
 pub mod cli;
+pub mod error;
+#[cfg(test)]
+mod fixtures;
+pub mod flac;
+pub mod index;
+pub mod matroska;
+pub mod media;
 pub mod scan;
+pub mod mp4;
 pub mod musicfile;
+pub mod ogg;
+pub mod photo;
+pub mod photofile;
+pub mod query;
+pub mod riff;
 pub mod search;
+pub mod stats;
+pub mod tag;
+pub mod videofile;
+pub mod vorbis;
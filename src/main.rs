@@ -1,7 +1,9 @@
+use medman::catalog::Catalog;
 use medman::cli::CliArguments;
-use medman::musicfile::MusicFile;
-use medman::scan::scan;
-use medman::search::{parse_request, search};
+use medman::scan::{default_workers, ScanReport};
+use medman::search::{parse_request, search, str_to_search_field, Hit};
+use medman::stats::facets;
+use medman::tag::{apply_changes, is_catalog_only, parse_tag_arguments, plan_changes};
 use std::env;
 use std::io;
 use std::path::Path;
@@ -25,36 +27,126 @@ fn help() {
     println!("medman <command> <arguments>");
     println!();
     println!("COMMANDES ET ARGUMENTS :");
-    println!("    scan <path>                       Analyse récursivement le répertoire ayant pour chemin 'path' afin de collecter les fichiers supportés (l’analyse extrait les métadonnées du fichier)");
+    println!("    scan <path>                       Analyse récursivement le répertoire ayant pour chemin 'path' afin de collecter les fichiers supportés (l’analyse extrait les métadonnées du fichier). Formats supportés : mp3, FLAC, Ogg Vorbis, Opus, M4A, WAV, AIFF, JPEG, TIFF, PNG (photos), MP4, MOV, MKV, WebM (vidéos)");
     println!("    search \"<path> champ1:valeur1      Effectue une recherche sur les données gérées dans les médias de 'path'. Le format de la requete est inspirée d’une partie de la syntaxe");
-    println!("           champ2:valeur2 ...\"         de l'outil de recherche par mots-clé Apache Lucene. Les sous requêtes sont séparées par des espace.");
-    println!("                                      Toutefois, les champs et valeurs des sous requetes NE DOIVENT PAS COMPORTER D'ESPACE. REMPLACER LES ESPACES PAR DES UNDESCORE (_)");
-    println!("                                      L'ensemble des champs de recherche possible sont : path, size, title, author, duration, album, year, genre.");
+    println!("           champ2:valeur2 ...\"         de l'outil de recherche par mots-clé Apache Lucene. Les sous requêtes sont séparées par des espace et combinées par un ET implicite.");
+    println!("                                      Les opérateurs AND, OR, NOT (ou &&, ||, !), les préfixes + (obligatoire) et - (interdit) et les parenthèses sont supportés.");
+    println!("                                      Les valeurs comportant des espaces doivent être placées entre guillemets : author:\"Miles Davis\"");
+    println!("                                      L'ensemble des champs de recherche possible sont : path, size, title, author, duration, album, year, genre,");
+    println!("                                      album_artist, track, disc, composer, comment, keyword (ou tag : mots-clés de l'utilisateur),");
+    println!("                                      bitrate (débit moyen en kbit/s), bitrate_mode (vbr ou cbr, mp3), sample_rate (Hz), bit_depth,");
+    println!("                                      channels, mpeg_version (1, 2 ou 2.5, mp3),");
+    println!("                                      kind (type de média : audio, photo ou video), et pour les photos : camera (appareil), lens (objectif),");
+    println!("                                      taken (date de prise de vue), width, height, orientation, latitude, longitude (coordonnées GPS) ;");
+    println!("                                      pour les vidéos : title, duration, width, height, frame_rate (ou fps : images par seconde, arrondi),");
+    println!("                                      codec (codecs vidéo et audio : h264, hevc, vp9, av1, aac, opus...).");
+    println!("                                      Un terme sans champ (beatles, \"abbey road\") est recherché dans tous les champs texte ; les résultats sont");
+    println!("                                      alors classés selon le champ où il a été trouvé (title > author > album > genre, keyword > path).");
+    println!("                                      Les champs numériques (size, duration, year, taken, latitude...) acceptent les intervalles ([min TO max],");
+    println!("                                      {{min TO max}}, * pour une borne ouverte) et les comparaisons (<, <=, >, >=). Les tailles acceptent les unités");
+    println!("                                      KB, MB, GB, KiB, MiB, GiB, les dates le format AAAA-MM-JJThh:mm:ss à la précision voulue (taken:[2019 TO 2020-06])");
+    println!("                                      et les coordonnées les degrés décimaux, négatifs au sud et à l'ouest (latitude:[48.8 TO 48.9]).");
+    println!("                                      Les champs texte sont comparés sans tenir compte de la casse ni des accents et acceptent les jokers * et ?");
+    println!("                                      (title:love*). Le préfixe = active le mode exact (title:=Yesterday). Le champ path accepte les motifs glob");
+    println!("                                      relatifs au répertoire scanné (path:\"jazz/**/*.mp3\").");
+    println!("                                      Le suffixe ~ (ou ~N) effectue une recherche approchée à 2 (ou N) erreurs près (title:yesterdy~)");
+    println!("                                      et une valeur entre / est une expression régulière (title:/lo+ve.*/).");
+    println!("                                      Les résultats sont classés par pertinence ; l'option --json les affiche au format JSON avec leur score.");
+    println!("                                      Le suffixe ^B pondère une clause ou un groupe dans le calcul du score (title:love^2).");
+    println!("                                      Les options sort:champ [asc|desc],... (score pour la pertinence), limit:N et offset:N trient");
+    println!("                                      et paginent les résultats : sort:year desc,title asc limit:50 offset:100");
     println!("    write2md <path> \"<filename>        Génère un fichier Markdown contenant le résultat de la dernière requête éffectuée.");
     println!("             search champ1:valeur1 ...\"");
+    println!("    stats <path> \"<champ> [requête]\"  Regroupe les médias de 'path' satisfaisant la requête (tous les médias sans requête) selon la");
+    println!("                                      valeur du champ (par répertoire pour path) et affiche le nombre de fichiers, la durée totale");
+    println!("                                      et moyenne et la taille totale de chaque groupe (au format JSON avec --json). Alias : facets.");
+    println!("    tag <path> \"<requête>             Modifie les champs des médias de 'path' satisfaisant la requête et les écrit dans leurs tags (ID3v2 en mp3, WAV et AIFF, commentaires Vorbis en FLAC)");
+    println!("        champ1=valeur1 ...\"           (une valeur vide supprime le champ). Les champs modifiables sont : title, author, album, year, genre,");
+    println!("                                      album_artist, track, disc, composer, comment, keyword. Si 'path' est un catalogue JSON, il est mis à jour.");
+    println!("                                      L'option --dry-run affiche les modifications (ancienne et nouvelle valeur) sans modifier les fichiers.");
+    println!("                                      Mots-clés : keyword=a,b remplace la liste, keyword+=a l'enrichit et keyword-=a la réduit ; ils sont");
+    println!("                                      écrits dans une frame TXXX:KEYWORDS, ou seulement dans le catalogue avec l'option --catalog-only.");
+    println!("    save <path> <catalog>             Analyse le répertoire 'path' et sauvegarde le résultat dans le catalogue JSON 'catalog'.");
+    println!("                                      L'index de recherche est sauvegardé à côté du catalogue (catalog.index.json).");
+    println!("    load <catalog>                    Charge et affiche le contenu du catalogue JSON 'catalog'.");
+    println!("    rescan <catalog>                  Met à jour le catalogue JSON 'catalog' en ne relisant que les fichiers ajoutés ou modifiés.");
+    println!("                                      Pour les commandes search et write2md, 'path' peut être un catalogue JSON : la recherche");
+    println!("                                      est alors effectuée sur le catalogue sauvegardé, sans réanalyser le répertoire.");
     println!();
     println!("EXEMPLES");
     println!("    scan      ->   scan /tmp/music_files/");
     println!("    search    ->   search \"/tmp/music_files/ title:MyFavMusic duration:2min45s\"");
+    println!("    search    ->   search /tmp/music_files/ 'author:\"My Artist\" title:MyFavMusic'");
     println!("    write2md  ->   write2md /tmp/music_files/ \"my_research_result.md search title:MyFavMusic duration:2min45s\"");
+    println!("    stats     ->   stats catalog.json \"genre year:[1990 TO 1999]\"");
+    println!("    tag       ->   tag catalog.json 'album:\"Kind of Blue\" genre=Jazz year=1959' --dry-run");
+    println!("    tag       ->   tag catalog.json 'genre:Rock keyword+=workout' --catalog-only");
+    println!("    save      ->   save /tmp/music_files/ catalog.json");
+    println!("    load      ->   load catalog.json");
+    println!("    rescan    ->   rescan catalog.json");
+    println!("    save      ->   save /tmp/music_files/ catalog.json --jobs 4");
+    println!("    search    ->   search catalog.json \"title:MyFavMusic\"");
+    println!("    search    ->   search catalog.json \"genre:Rock sort:year desc,title limit:50 offset:100\"");
+    println!("    search    ->   search catalog.json \"camera:canon* taken:[2019 TO 2020]\"");
+    println!("    search    ->   search catalog.json \"height:>=1080 codec:hevc\"");
+    println!("    search    ->   search catalog.json \"kind:audio bitrate:<128\"");
 
     println!();
     println!();
 
     println!("MODE INTERACTIF");
     println!("COMMANDES");
-    println!("    scan <path>                   Analyse récursivement le répertoire ayant pour chemin 'path' afin de collecter les fichiers supportés (l’analyse extrait les métadonnées du fichier)");
+    println!("    scan [-j N] <path>            Analyse récursivement le répertoire ayant pour chemin 'path' afin de collecter les fichiers supportés (l’analyse extrait les métadonnées du fichier). Formats supportés : mp3, FLAC, Ogg Vorbis, Opus, M4A, WAV, AIFF, JPEG, TIFF, PNG (photos), MP4, MOV, MKV, WebM (vidéos)");
     println!("    search champ1:valeur1         Effectue une recherche sur les données gérées. Le format de la requete est inspirée d’une partie de la syntaxe de l'outil de recherche par mots-clé Apache Lucene.");
-    println!("           champ2:valeur2         Chaque sous requête doit donc être séparée d'un espace.");
-    println!("           ...                    L'ensemble des champs de recherche possible sont : path, size, title, author, duration, album, year, genre.");
+    println!("           champ2:valeur2         Chaque sous requête doit donc être séparée d'un espace. Les sous requêtes sont combinées par un ET implicite,");
+    println!("                                  ou par les opérateurs AND, OR, NOT (ou &&, ||, !), les préfixes + et - et des parenthèses.");
+    println!("           ...                    L'ensemble des champs de recherche possible sont : path, size, title, author, duration, album, year, genre,");
+    println!("                                  album_artist, track, disc, composer, comment, keyword (ou tag), bitrate, bitrate_mode,");
+    println!("                                  sample_rate, bit_depth, channels, mpeg_version, kind (type de média),");
+    println!("                                  camera, lens, taken, width, height, orientation, latitude, longitude (photos),");
+    println!("                                  frame_rate (ou fps), codec (vidéos).");
+    println!("                                  Un terme sans champ (beatles abbey) est recherché dans tous les champs texte.");
+    println!("                                  Les champs numériques acceptent les intervalles et les comparaisons : year:[1990 TO 1999] size:>5MB.");
+    println!("                                  Les dates et coordonnées s'écrivent taken:[2019 TO 2020-06] latitude:>48.5");
+    println!("                                  Les champs texte acceptent les jokers (title:love* author:?eatles) et le mode exact (title:=Yesterday).");
+    println!("                                  Recherche approchée : title:yesterdy~ (ou ~N), expression régulière : title:/lo+ve.*/");
+    println!("                                  Les résultats sont classés par pertinence ; ^B pondère une clause (title:love^2).");
+    println!("                                  Options de tri et de pagination : sort:year desc,title asc limit:50 offset:100");
+    println!("                                  Les résultats sont affichés par pages de 10.");
+    println!("    next / prev / page <n>        Affiche la page suivante, précédente ou la page n des résultats de la dernière recherche.");
     println!("    write2md <filename>           Génère un fichier Markdown contenant le résultat de la dernière requête éffectuée.");
+    println!("    stats <champ> [requête]       Regroupe les médias satisfaisant la requête (tous les médias sans requête) selon la valeur");
+    println!("                                  du champ et affiche le nombre de fichiers, la durée totale et moyenne et la taille totale de chaque groupe.");
+    println!("    tag [--dry-run] <requête>     Modifie les champs des médias satisfaisant la requête et les écrit dans leurs tags (ID3v2 en mp3, WAV et AIFF, commentaires Vorbis en FLAC).");
+    println!("        champ1=valeur1 ...        Avec --dry-run, les modifications sont seulement affichées. Mots-clés : keyword=a,b, keyword+=a,");
+    println!("                                  keyword-=a ; avec --catalog-only, ils ne sont conservés que dans le catalogue.");
+    println!("    save <catalog>                Sauvegarde les fichiers scannés dans le catalogue JSON 'catalog'.");
+    println!("    load <catalog>                Charge le catalogue JSON 'catalog' : les recherches suivantes portent sur son contenu.");
+    println!("    rescan [-j N]                 Met à jour les fichiers scannés en ne relisant que les fichiers ajoutés ou modifiés.");
+    println!("                                  Pour scan et rescan, -j N (ou --jobs N) fixe le nombre de threads de lecture (par défaut, le nombre de cœurs).");
     println!("    help                          Affiche le message d'aide.");
     println!("    quit                          Met fin au programmme.");
     println!();
     println!("EXEMPLES");
     println!("    scan      ->   scan /tmp/music_files/");
     println!("    search    ->   search path:/tmp/music_files/music1.mp3 title:MyFavMusic duration:2min45s");
+    println!("    search    ->   search (genre:Rock OR genre:Pop) -author:\"My Artist\"");
+    println!("    search    ->   search year:[1990 TO 1999] duration:<3min size:>5MB");
+    println!("    search    ->   search title:love* path:\"jazz/**/*.mp3\"");
+    println!("    search    ->   search beatles \"abbey road\"");
+    println!("    search    ->   search genre:Jazz sort:year desc,title asc");
+    println!("    search    ->   search kind:photo camera:canon* taken:[2019 TO 2020]");
+    println!("    search    ->   search kind:video height:>=1080 codec:hevc");
+    println!("    search    ->   search bitrate:<128 bitrate_mode:cbr");
+    println!("    page      ->   page 3");
     println!("    write2md  ->   write2md my_research_result.md");
+    println!("    stats     ->   stats album author:\"Miles Davis\"");
+    println!("    tag       ->   tag --dry-run author:Beatles author=\"The Beatles\" album_artist=\"The Beatles\"");
+    println!("    tag       ->   tag --catalog-only title:love* keyword+=wedding-set");
+    println!("    save      ->   save catalog.json");
+    println!("    load      ->   load catalog.json");
+    println!("    rescan    ->   rescan");
+    println!("    scan      ->   scan -j 4 /tmp/music_files/");
     println!();
 
 }
@@ -62,7 +154,7 @@ fn help() {
 
 // Affiche un message d'erreur puis l'aide
 pub fn err_help() {
-    eprintln!("Commande non supportée. Les commandes supportées sont : scan, search et write2md");
+    eprintln!("Commande non supportée. Les commandes supportées sont : scan, search, write2md, stats, tag, save, load et rescan");
     eprintln!();
     help();
     panic!("ERREUR");
@@ -70,8 +162,152 @@ pub fn err_help() {
 }
 
 
+// Renvoie le catalogue correspondant au chemin passé en paramètre :
+// si le chemin désigne un fichier, il s'agit d'un catalogue JSON sauvegardé qui est chargé,
+// sinon le répertoire est scanné avec 'workers' threads de lecture
+fn load_or_scan(path: &Path, workers: usize) -> Catalog {
+    if path.is_file() {
+        match Catalog::load(path) {
+            Ok(catalog) => catalog,
+            Err(e) => panic!("Impossible de charger le catalogue {:?} : {}", path, e),
+        }
+    } else {
+        let (catalog, report) = Catalog::scan(path, workers);
+        print_skipped(&report);
+        catalog
+    }
+}
+
+
+// Lit l'option -j N (ou --jobs N) en tête des arguments d'une commande scan ou rescan du mode interactif.
+// Renvoie le nombre de threads de lecture (par défaut, le nombre de cœurs) et la suite des arguments
+fn parse_jobs(args: &str) -> Result<(usize, &str), String> {
+    let args = args.trim_start();
+    let rest = match args.strip_prefix("--jobs ").or_else(|| args.strip_prefix("-j ")) {
+        Some(rest) => rest.trim_start(),
+        None => return Ok((default_workers(), args)),
+    };
+    let (value, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+    match value.parse::<usize>() {
+        Ok(workers) => Ok((workers.max(1), rest.trim_start())),
+        Err(_) => Err(format!("Nombre de threads incorrect : {}", value)),
+    }
+}
+
+
+// Affiche sur la sortie d'erreur les fichiers ignorés lors d'une analyse et la raison de chaque erreur
+fn print_skipped(report: &ScanReport) {
+    if !report.skipped.is_empty() {
+        eprintln!("Fichier(s) ignoré(s) lors de l'analyse :");
+        for error in &report.skipped {
+            eprintln!("    {}", error);
+        }
+    }
+}
+
+
+// Affiche les résultats d'une requête, du plus pertinent au moins pertinent,
+// sous forme lisible ou au format JSON (le score de chaque résultat est alors un champ)
+fn print_results(results: &[Hit], json: bool) {
+    if json {
+        match serde_json::to_string_pretty(results) {
+            Ok(js) => println!("{}", js),
+            Err(e) => eprintln!("Impossible de convertir les résultats en JSON : {}", e),
+        }
+    } else {
+        println!("Résultats de votre requête : ");
+        for hit in results {
+            println!("Score : {:.3}", hit.score);
+            println!("{:#?}", hit.media);
+        }
+    }
+}
+
+
+// Regroupe les médias du catalogue satisfaisant la requête selon un champ et affiche les statistiques
+// de chaque groupe (nombre de fichiers, durée totale et moyenne, taille totale). 'args' est de la forme
+// "champ [requête]" ; sans requête, tout le catalogue est pris en compte
+fn print_stats(catalog: &Catalog, args: &str, json: bool) -> Result<(), String> {
+    let (field_name, request) = args.trim().split_once(' ').unwrap_or((args.trim(), ""));
+    let field = match str_to_search_field(field_name) {
+        Some(field) => field,
+        None => return Err(format!("Champ de regroupement inconnu : {}", field_name)),
+    };
+    let stats = if request.trim().is_empty() {
+        facets(catalog.files(), &field, catalog.root())
+    } else {
+        let results = search(catalog, &parse_request(request)?);
+        facets(results.iter().map(|hit| &hit.media), &field, catalog.root())
+    };
+    if json {
+        match serde_json::to_string_pretty(&stats) {
+            Ok(js) => println!("{}", js),
+            Err(e) => eprintln!("Impossible de convertir les statistiques en JSON : {}", e),
+        }
+    } else {
+        println!("Statistiques par {} :", field.name());
+        for facet in &stats.groups {
+            println!("    {}", facet);
+        }
+        println!("{}", stats.total);
+    }
+    Ok(())
+}
+
+
+// Applique la commande tag ("<requête> champ=valeur ...") aux médias du catalogue satisfaisant la requête :
+// affiche les modifications prévues (ancienne et nouvelle valeur de chaque champ) puis, sauf en mode
+// 'dry_run', les écrit dans les tags ID3v2 des fichiers (sauf en mode 'catalog_only', réservé aux
+// mots-clés) et met à jour le catalogue. Renvoie le nombre de médias modifiés
+fn run_tag(catalog: &mut Catalog, args: &str, dry_run: bool, catalog_only: bool) -> Result<usize, String> {
+    let (request, edits) = parse_tag_arguments(args)?;
+    if catalog_only && !is_catalog_only(&edits) {
+        return Err("seuls les mots-clés (keyword) peuvent être modifiés dans le catalogue seulement".to_string());
+    }
+    let results = search(catalog, &parse_request(&request)?);
+    let changes = plan_changes(results.iter().map(|hit| &hit.media), &edits);
+    if changes.is_empty() {
+        println!("Aucune modification à effectuer.");
+        return Ok(0);
+    }
+    for change in &changes {
+        println!("{}", change);
+    }
+    if dry_run {
+        println!("{} fichier(s) à modifier (--dry-run : aucun fichier n'a été modifié).", changes.len());
+        return Ok(0);
+    }
+    let (count, errors) = apply_changes(catalog, &changes, catalog_only);
+    for error in &errors {
+        eprintln!("{}", error);
+    }
+    println!("{} média(s) modifié(s){}.", count, if catalog_only { " dans le catalogue" } else { "" });
+    Ok(count)
+}
+
+
+// Nombre de résultats affichés par page en mode interactif
+const PAGE_SIZE: usize = 10;
+
+
+// Affiche la page 'page' (numérotée à partir de 0) des résultats d'une requête en mode interactif
+fn print_page(results: &[Hit], page: usize) {
+    if results.is_empty() {
+        println!("Aucun résultat pour votre requête.");
+        return;
+    }
+    let start = page * PAGE_SIZE;
+    let end = (start + PAGE_SIZE).min(results.len());
+    print_results(&results[start..end], false);
+    println!("Résultats {} à {} sur {} (page {}/{}).", start + 1, end, results.len(), page + 1, results.len().div_ceil(PAGE_SIZE));
+    if results.len() > PAGE_SIZE {
+        println!("Commandes next, prev et page <n> pour parcourir les résultats.");
+    }
+}
+
+
 // Génère un fichier Markdown contenant le résultat d’une requête
-fn to_markdown(results: Vec<MusicFile>, file_path: &str, request:&str) {
+fn to_markdown(results: Vec<Hit>, file_path: &str, request:&str) {
     // Création du fichier résultat
     let file = File::create(file_path).unwrap();
     let mut md = Markdown::new(file);
@@ -108,30 +344,31 @@ fn main() {
             "scan" => // La commande à exécuter est le scan
             {
                 // Scan du répertoire
-                let music_files = scan(args.path());
+                let (catalog, report) = Catalog::scan(args.path(), args.get_workers());
                 // Affichange des médias scannés
                 println!("Fichiers scannés :\n");
-                for music_file in music_files.clone() {
-                    println!("{:?}", music_file);
-                }        
+                for media in catalog.files() {
+                    println!("{:?}", media);
+                }
+                println!();
+                println!("Analyse de {:?} terminée : {}.", catalog.root(), report);
+                print_skipped(&report);
             },
 
             "search" => // La commande à exécuter est le search
             {
-                // Scan du répertoire
-                let music_files = scan(args.path());
+                // Scan du répertoire (ou chargement du catalogue)
+                let catalog = load_or_scan(args.path(), args.get_workers());
                 // Conversion des arguments (qui représentent la requête)
                 match args.get_arguments() {
-                    Some(string_args) => 
-                    {
-                        let vec_req = parse_request(string_args.as_str());
-                        // Recherche dans la liste des fichiers scannés
-                        let req_results = search(music_files.clone(), vec_req);
-                        // Affichage du résultat
-                        println!("Résultats de votre requête : ");
-                        for mf in req_results.clone() {
-                            println!("{:#?}", mf);
-                        }        
+                    Some(string_args) => match parse_request(string_args.as_str()) {
+                        Ok(request) => {
+                            // Recherche dans la liste des fichiers scannés
+                            let req_results = search(&catalog, &request);
+                            // Affichage du résultat
+                            print_results(&req_results, args.is_json());
+                        },
+                        Err(e) => eprintln!("Requête incorrecte : {}", e),
                     },
                     None => err_help(),
                 }
@@ -139,8 +376,8 @@ fn main() {
 
             "write2md" => // La commande à exécuter est le write2md
             {
-                // Scan du répertoire
-                let music_files = scan(args.path());
+                // Scan du répertoire (ou chargement du catalogue)
+                let catalog = load_or_scan(args.path(), args.get_workers());
                 match args.get_arguments() {
                     Some(string_args) => 
                     {
@@ -153,11 +390,15 @@ fn main() {
                             (Some(file_path), Some("search"), Some(search_args)) =>
                             {
                                 // Conversion des arguments de search en requête
-                                let vec_req = parse_request(search_args);
-                                // Recherche dans la liste des fichiers scannés
-                                let req_results = search(music_files.clone(), vec_req);
-                                // Génération du résultat au format markdown
-                                to_markdown(req_results, file_path, ("search ".to_string()+search_args).as_str());
+                                match parse_request(search_args) {
+                                    Ok(request) => {
+                                        // Recherche dans la liste des fichiers scannés
+                                        let req_results = search(&catalog, &request);
+                                        // Génération du résultat au format markdown
+                                        to_markdown(req_results, file_path, ("search ".to_string()+search_args).as_str());
+                                    },
+                                    Err(e) => eprintln!("Requête incorrecte : {}", e),
+                                }
 
                             },
                             _ => err_help(),
@@ -168,6 +409,87 @@ fn main() {
 
             },
 
+            "stats" | "facets" => // La commande à exécuter est le stats
+            {
+                // Scan du répertoire (ou chargement du catalogue) puis agrégation
+                let catalog = load_or_scan(args.path(), args.get_workers());
+                match args.get_arguments() {
+                    Some(string_args) => if let Err(e) = print_stats(&catalog, &string_args, args.is_json()) {
+                        eprintln!("Requête incorrecte : {}", e);
+                    },
+                    None => err_help(),
+                }
+            },
+
+            "tag" => // La commande à exécuter est le tag
+            {
+                // Scan du répertoire (ou chargement du catalogue) puis modification des tags
+                let mut catalog = load_or_scan(args.path(), args.get_workers());
+                match args.get_arguments() {
+                    Some(_) if args.is_catalog_only() && !args.path().is_file() =>
+                        eprintln!("L'option --catalog-only nécessite un catalogue JSON."),
+                    Some(string_args) => match run_tag(&mut catalog, &string_args, args.is_dry_run(), args.is_catalog_only()) {
+                        // Le catalogue éventuellement chargé est mis à jour
+                        Ok(count) if count > 0 && args.path().is_file() => {
+                            if let Err(e) = catalog.save(args.path()) {
+                                panic!("Impossible de sauvegarder le catalogue {:?} : {}", args.path(), e);
+                            }
+                        },
+                        Ok(_) => (),
+                        Err(e) => eprintln!("Requête incorrecte : {}", e),
+                    },
+                    None => err_help(),
+                }
+            },
+
+            "save" => // La commande à exécuter est le save
+            {
+                match args.get_arguments() {
+                    Some(catalog_path) =>
+                    {
+                        // Scan du répertoire puis sauvegarde du catalogue
+                        let (catalog, report) = Catalog::scan(args.path(), args.get_workers());
+                        println!("Analyse de {:?} terminée : {}.", catalog.root(), report);
+                        print_skipped(&report);
+                        match catalog.save(Path::new(&catalog_path)) {
+                            Ok(()) => println!("{} fichier(s) sauvegardé(s) dans le catalogue {}.", catalog.len(), catalog_path),
+                            Err(e) => panic!("Impossible de sauvegarder le catalogue {} : {}", catalog_path, e),
+                        }
+                    },
+                    None => err_help(),
+                }
+            },
+
+            "load" => // La commande à exécuter est le load
+            {
+                // Chargement du catalogue puis affichage des médias qu'il contient
+                match Catalog::load(args.path()) {
+                    Ok(catalog) => {
+                        println!("Catalogue de {:?} ({} fichier(s)) :\n", catalog.root(), catalog.len());
+                        for media in catalog.files() {
+                            println!("{:?}", media);
+                        }
+                    },
+                    Err(e) => panic!("Impossible de charger le catalogue {:?} : {}", args.path(), e),
+                }
+            },
+
+            "rescan" => // La commande à exécuter est le rescan
+            {
+                // Chargement du catalogue, réanalyse incrémentale puis sauvegarde
+                match Catalog::load(args.path()) {
+                    Ok(mut catalog) => {
+                        let report = catalog.rescan(args.get_workers());
+                        println!("Analyse de {:?} terminée : {}.", catalog.root(), report);
+                        print_skipped(&report);
+                        if let Err(e) = catalog.save(args.path()) {
+                            panic!("Impossible de sauvegarder le catalogue {:?} : {}", args.path(), e);
+                        }
+                    },
+                    Err(e) => panic!("Impossible de charger le catalogue {:?} : {}", args.path(), e),
+                }
+            },
+
             _ => err_help(),
         }
 
@@ -176,9 +498,10 @@ fn main() {
         help();
         println!();
 
-        let mut scanned_files : Vec<MusicFile> = Vec::new(); // Fichiers scannés
+        let mut catalog = Catalog::new(Path::new(""), Vec::new()); // Fichiers scannés
         let mut prec_request: String = String::new(); // Requête précédente
-        let mut req_results: Vec<MusicFile> = Vec::new(); // Résultats d'une requête
+        let mut req_results: Vec<Hit> = Vec::new(); // Résultats d'une requête
+        let mut page: usize = 0; // Page des résultats affichée
 
         loop {
             // Lecture de la commande
@@ -196,36 +519,56 @@ fn main() {
                     // Si la commande saisie est scan
                     if cmd == Some("scan") {
                         // Désempaque l'argument et Scanne le répertoire passé en paramètre
-                        match args {
-                            Some(args) => {
-                                scanned_files = scan(Path::new(args));
+                        match args.map(parse_jobs) {
+                            Some(Ok((workers, path))) if !path.is_empty() => {
+                                let (scanned, report) = Catalog::scan(Path::new(path), workers);
+                                catalog = scanned;
                                 println!("Fichiers scannés :\n");
-                                for music_file in scanned_files.clone() {
-                                    println!("{:#?}", music_file);
+                                for media in catalog.files() {
+                                    println!("{:#?}", media);
                                 }
+                                println!("Analyse de {:?} terminée : {}.", catalog.root(), report);
+                                print_skipped(&report);
                             },
-
-                            None => eprintln!("Requête incorrect !"),
+                            Some(Err(e)) => eprintln!("{}", e),
+                            _ => eprintln!("Requête incorrect !"),
                         }
     
                     } else if cmd == Some("search")  { // Sinon s'il s'agit de search, lance la recherche
-                        match scanned_files.clone().len() {
+                        match catalog.len() {
                             0 => eprintln!("Aucun répertoire scanné. Vous devez scanner un répertoire au préalable."),
                             _ => {
                                 match args {
-                                    Some(args) => {
-                                        prec_request = buffer.clone();
-                                        let vec_req = parse_request(args);
-                                        req_results = search(scanned_files.clone(), vec_req);
-                                        println!("Résultats de votre requête : ");
-                                        for mf in req_results.clone() {
-                                            println!("{:#?}", mf);
-                                        }
+                                    Some(args) => match parse_request(args) {
+                                        Ok(request) => {
+                                            prec_request = buffer.clone();
+                                            req_results = search(&catalog, &request);
+                                            page = 0;
+                                            print_page(&req_results, page);
+                                        },
+                                        Err(e) => eprintln!("Requête incorrecte : {}", e),
                                     },
                                     None => eprintln!("Requête incorrect !"),
                                 }
                             },
                         }
+                    } else if cmd == Some("next") || cmd == Some("prev") || cmd == Some("page") { // Pagination des résultats
+                        let page_count = req_results.len().div_ceil(PAGE_SIZE);
+                        let requested = match (cmd, args) {
+                            (Some("next"), _) => Some(page + 1),
+                            (Some("prev"), _) => page.checked_sub(1),
+                            (_, Some(args)) => args.trim().parse::<usize>().ok().and_then(|n| n.checked_sub(1)),
+                            _ => None,
+                        };
+                        match requested {
+                            _ if req_results.is_empty() => eprintln!("Aucun résultat à afficher. Veuillez exécuter une requête au préalable"),
+                            Some(requested) if requested < page_count => {
+                                page = requested;
+                                print_page(&req_results, page);
+                            },
+                            _ => eprintln!("Page inexistante : les résultats comportent {} page(s).", page_count),
+                        }
+
                     } else if cmd == Some("write2md") { // Write2md
                         // Vérifie si une recherche a déjà été effectuée
                         match req_results.len() {
@@ -236,6 +579,73 @@ fn main() {
                             }
                         }
 
+                    } else if cmd == Some("stats") || cmd == Some("facets") { // Statistiques par groupe
+                        match (catalog.len(), args) {
+                            (0, _) => eprintln!("Aucun répertoire scanné. Vous devez scanner un répertoire au préalable."),
+                            (_, Some(args)) => if let Err(e) = print_stats(&catalog, args, false) {
+                                eprintln!("Requête incorrecte : {}", e);
+                            },
+                            (_, None) => eprintln!("Requête incorrect !"),
+                        }
+
+                    } else if cmd == Some("tag") { // Modification des tags
+                        match (catalog.len(), args) {
+                            (0, _) => eprintln!("Aucun répertoire scanné. Vous devez scanner un répertoire au préalable."),
+                            (_, Some(args)) => {
+                                // Options --dry-run et --catalog-only, dans un ordre quelconque
+                                let (mut args, mut dry_run, mut catalog_only) = (args.trim_start(), false, false);
+                                loop {
+                                    if let Some(rest) = args.strip_prefix("--dry-run") {
+                                        dry_run = true;
+                                        args = rest.trim_start();
+                                    } else if let Some(rest) = args.strip_prefix("--catalog-only") {
+                                        catalog_only = true;
+                                        args = rest.trim_start();
+                                    } else {
+                                        break;
+                                    }
+                                }
+                                if let Err(e) = run_tag(&mut catalog, args, dry_run, catalog_only) {
+                                    eprintln!("Requête incorrecte : {}", e);
+                                }
+                            },
+                            (_, None) => eprintln!("Requête incorrect !"),
+                        }
+
+                    } else if cmd == Some("save") { // Sauvegarde du catalogue
+                        match (catalog.len(), args) {
+                            (0, _) => eprintln!("Aucun répertoire scanné. Vous devez scanner un répertoire au préalable."),
+                            (_, Some(args)) => match catalog.save(Path::new(args)) {
+                                Ok(()) => println!("{} fichier(s) sauvegardé(s) dans le catalogue {}.", catalog.len(), args),
+                                Err(e) => eprintln!("Impossible de sauvegarder le catalogue {} : {}", args, e),
+                            },
+                            (_, None) => eprintln!("Requête incorrect !"),
+                        }
+
+                    } else if cmd == Some("load") { // Chargement d'un catalogue
+                        match args {
+                            Some(args) => match Catalog::load(Path::new(args)) {
+                                Ok(loaded) => {
+                                    catalog = loaded;
+                                    println!("Catalogue de {:?} chargé : {} fichier(s).", catalog.root(), catalog.len());
+                                },
+                                Err(e) => eprintln!("Impossible de charger le catalogue {} : {}", args, e),
+                            },
+                            None => eprintln!("Requête incorrect !"),
+                        }
+
+                    } else if cmd == Some("rescan") { // Réanalyse incrémentale
+                        match (catalog.len(), parse_jobs(args.unwrap_or(""))) {
+                            (0, _) => eprintln!("Aucun répertoire scanné. Vous devez scanner un répertoire au préalable."),
+                            (_, Ok((workers, ""))) => {
+                                let report = catalog.rescan(workers);
+                                println!("Analyse de {:?} terminée : {}.", catalog.root(), report);
+                                print_skipped(&report);
+                            },
+                            (_, Ok(_)) => eprintln!("Requête incorrect !"),
+                            (_, Err(e)) => eprintln!("{}", e),
+                        }
+
                     } else if cmd == Some("help") { // Message d'aide
                         help();
 
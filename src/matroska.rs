@@ -0,0 +1,347 @@
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::time::Duration;
+use crate::error::MedmanError;
+use crate::videofile::VideoFile;
+
+
+// Identifiants des éléments EBML utilisés
+const EBML_HEADER: u32 = 0x1a45_dfa3;
+const DOC_TYPE: u32 = 0x4282;
+const SEGMENT: u32 = 0x1853_8067;
+const INFO: u32 = 0x1549_a966;
+const TIMESTAMP_SCALE: u32 = 0x2a_d7b1;
+const DURATION: u32 = 0x4489;
+const TITLE: u32 = 0x7ba9;
+const TRACKS: u32 = 0x1654_ae6b;
+const TRACK_ENTRY: u32 = 0xae;
+const TRACK_TYPE: u32 = 0x83;
+const CODEC_ID: u32 = 0x86;
+const DEFAULT_DURATION: u32 = 0x23_e383;
+const VIDEO: u32 = 0xe0;
+const PIXEL_WIDTH: u32 = 0xb0;
+const PIXEL_HEIGHT: u32 = 0xba;
+
+// Types de pistes
+const VIDEO_TRACK: u64 = 1;
+const AUDIO_TRACK: u64 = 2;
+
+// Échelle de temps par défaut du segment (durée en millisecondes)
+const DEFAULT_TIMESTAMP_SCALE: u64 = 1_000_000;
+
+// Taille maximale des éléments lus en mémoire (en-tête, informations du segment, pistes)
+const MAX_ELEMENT_SIZE: u64 = 16 << 20;
+
+// Durée maximale acceptée (un an) : au-delà, la durée lue est considérée comme incorrecte
+const MAX_DURATION: Duration = Duration::from_secs(365 * 24 * 3600);
+
+
+// Complète la vidéo avec les métadonnées d'un fichier Matroska ou WebM : titre et durée lus dans
+// l'élément Info du segment, dimensions, nombre d'images par seconde et codec de la première piste vidéo,
+// codec de la première piste audio lus dans l'élément Tracks. Les éléments du segment sont parcourus
+// jusqu'à ce que ces deux éléments aient été lus, en sautant les autres (dont les données des clusters)
+pub(crate) fn read_matroska_metadata(video_file: &mut VideoFile) -> Result<(), MedmanError> {
+    let path = video_file.path.clone();
+    let error = |e: io::Error| match e.kind() {
+        io::ErrorKind::UnexpectedEof => MedmanError::InvalidMedia(path.clone(), "fichier Matroska tronqué".to_string()),
+        io::ErrorKind::InvalidData => MedmanError::InvalidMedia(path.clone(), e.to_string()),
+        _ => MedmanError::Io(path.clone(), e),
+    };
+    let mut reader = BufReader::new(File::open(&path).map_err(error)?);
+
+    // En-tête EBML : le type de document est "matroska" ou "webm"
+    let doc_type = match read_header(&mut reader).map_err(error)? {
+        Some((EBML_HEADER, size)) => {
+            let header = read_content(&mut reader, size).map_err(error)?;
+            children(&header).into_iter().find(|(id, _)| *id == DOC_TYPE).map(|(_, doc_type)| text(doc_type))
+        },
+        _ => return Err(MedmanError::InvalidMedia(path, "en-tête EBML absent".to_string())),
+    };
+    if !matches!(doc_type.as_deref(), Some("matroska") | Some("webm")) {
+        return Err(MedmanError::InvalidMedia(path, "type de document non supporté (matroska ou webm attendu)".to_string()));
+    }
+    match read_header(&mut reader).map_err(error)? {
+        Some((SEGMENT, _)) => (),
+        _ => return Err(MedmanError::InvalidMedia(path, "segment Matroska absent".to_string())),
+    }
+
+    let (mut info, mut tracks) = (None, None);
+    while info.is_none() || tracks.is_none() {
+        match read_header(&mut reader).map_err(error)? {
+            Some((INFO, size)) => info = Some(read_content(&mut reader, size).map_err(error)?),
+            Some((TRACKS, size)) => tracks = Some(read_content(&mut reader, size).map_err(error)?),
+            Some((_, Some(size))) => { reader.seek(SeekFrom::Current(size as i64)).map_err(error)?; },
+            // Élément de taille inconnue (cluster d'un flux en direct) : la suite du segment n'est pas parcourue
+            Some((_, None)) | None => break,
+        }
+    }
+    if info.is_none() && tracks.is_none() {
+        return Err(MedmanError::InvalidMedia(path, "éléments Info et Tracks absents".to_string()));
+    }
+    if let Some(info) = info {
+        fill_info(video_file, &info);
+    }
+    if let Some(tracks) = tracks {
+        fill_tracks(video_file, &tracks);
+    }
+    Ok(())
+}
+
+
+// Complète la vidéo avec le titre et la durée (nombre flottant, en unités de l'échelle de temps
+// du segment exprimée en nanosecondes) de l'élément Info. Une durée négative, non finie
+// ou supérieure à MAX_DURATION est ignorée
+fn fill_info(video_file: &mut VideoFile, info: &[u8]) {
+    let elements = children(info);
+    let scale = elements.iter().find(|(id, _)| *id == TIMESTAMP_SCALE)
+        .map(|(_, scale)| unsigned(scale))
+        .unwrap_or(DEFAULT_TIMESTAMP_SCALE);
+    for (id, data) in elements {
+        match id {
+            TITLE => video_file.title = text(data),
+            DURATION => {
+                let nanos = float(data).map(|duration| duration * scale as f64).unwrap_or(-1.0);
+                if let Ok(duration) = Duration::try_from_secs_f64(nanos / 1_000_000_000.0) {
+                    if duration <= MAX_DURATION {
+                        video_file.duration = duration;
+                    }
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+
+// Complète la vidéo avec les caractéristiques des premières pistes vidéo et audio de l'élément Tracks.
+// Le nombre d'images par seconde est déduit de la durée d'une image (DefaultDuration, en nanosecondes)
+fn fill_tracks(video_file: &mut VideoFile, tracks: &[u8]) {
+    for (_, entry) in children(tracks).into_iter().filter(|(id, _)| *id == TRACK_ENTRY) {
+        let elements = children(entry);
+        let element = |wanted: u32| elements.iter().find(|(id, _)| *id == wanted).map(|(_, data)| *data);
+        let codec = element(CODEC_ID).map(|codec_id| codec_name(&text(codec_id))).unwrap_or_default();
+        match element(TRACK_TYPE).map(unsigned) {
+            Some(VIDEO_TRACK) if video_file.video_codec.is_empty() => {
+                video_file.video_codec = codec;
+                if let Some(video) = element(VIDEO) {
+                    for (id, data) in children(video) {
+                        match id {
+                            PIXEL_WIDTH => video_file.width = unsigned(data) as u32,
+                            PIXEL_HEIGHT => video_file.height = unsigned(data) as u32,
+                            _ => (),
+                        }
+                    }
+                }
+                if let Some(frame_duration) = element(DEFAULT_DURATION).map(unsigned).filter(|duration| *duration > 0) {
+                    video_file.frame_rate = 1_000_000_000.0 / frame_duration as f64;
+                }
+            },
+            Some(AUDIO_TRACK) if video_file.audio_codec.is_empty() => video_file.audio_codec = codec,
+            _ => (),
+        }
+    }
+}
+
+
+// Nom usuel du codec correspondant à l'identifiant de codec d'une piste
+// (l'identifiant sans son préfixe, en minuscules, s'il n'est pas connu)
+fn codec_name(codec_id: &str) -> String {
+    match codec_id {
+        "V_MPEG4/ISO/AVC" => "h264",
+        "V_MPEGH/ISO/HEVC" => "hevc",
+        "V_AV1" => "av1",
+        "V_VP8" => "vp8",
+        "V_VP9" => "vp9",
+        "V_THEORA" => "theora",
+        "V_MPEG2" => "mpeg2",
+        "V_MPEG4/ISO/SP" | "V_MPEG4/ISO/ASP" | "V_MPEG4/ISO/AP" => "mpeg4",
+        "V_MJPEG" => "mjpeg",
+        "A_OPUS" => "opus",
+        "A_VORBIS" => "vorbis",
+        "A_FLAC" => "flac",
+        "A_ALAC" => "alac",
+        "A_AC3" => "ac3",
+        "A_EAC3" => "eac3",
+        "A_DTS" => "dts",
+        "A_TRUEHD" => "truehd",
+        "A_MPEG/L3" => "mp3",
+        "A_MPEG/L2" => "mp2",
+        id if id.starts_with("A_AAC") => "aac",
+        id if id.starts_with("A_PCM") => "pcm",
+        id => return id.split_once('_').map_or(id, |(_, name)| name).to_lowercase(),
+    }.to_string()
+}
+
+
+// Lit l'en-tête de l'élément suivant : identifiant et taille du contenu (None si elle est inconnue).
+// Renvoie None à la fin du fichier
+fn read_header<R: Read>(reader: &mut R) -> io::Result<Option<(u32, Option<u64>)>> {
+    let mut first = [0; 1];
+    match reader.read_exact(&mut first) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    // L'identifiant conserve son marqueur de longueur, il occupe au plus 4 octets
+    let (id, id_length) = read_vint(reader, first[0])?;
+    if id_length > 4 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "identifiant d'élément EBML incorrect"));
+    }
+    reader.read_exact(&mut first)?;
+    let (size, size_length) = read_vint(reader, first[0])?;
+    let marker = 1 << (7 * size_length);
+    // Une taille dont tous les bits valent 1 est inconnue
+    let size = size & (marker - 1);
+    Ok(Some((id as u32, if size == marker - 1 { None } else { Some(size) })))
+}
+
+
+// Lit la suite d'un entier de longueur variable commençant par l'octet 'first' : le nombre de zéros
+// de tête de cet octet est le nombre d'octets suivants. Renvoie l'entier (avec son marqueur de longueur)
+// et sa longueur
+fn read_vint<R: Read>(reader: &mut R, first: u8) -> io::Result<(u64, u32)> {
+    let length = first.leading_zeros() + 1;
+    if length > 8 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "entier EBML incorrect"));
+    }
+    let mut bytes = [0; 7];
+    let rest = &mut bytes[..length as usize - 1];
+    reader.read_exact(rest)?;
+    let value = rest.iter().fold(first as u64, |value, byte| value << 8 | *byte as u64);
+    Ok((value, length))
+}
+
+
+// Lit le contenu d'un élément de taille 'size'
+fn read_content<R: Read>(reader: &mut R, size: Option<u64>) -> io::Result<Vec<u8>> {
+    let size = size.filter(|size| *size <= MAX_ELEMENT_SIZE)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "taille d'élément EBML incorrecte"))?;
+    let mut content = Vec::new();
+    reader.take(size).read_to_end(&mut content)?;
+    if (content.len() as u64) < size {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(content)
+}
+
+
+// Éléments contenus dans 'data' : identifiant et contenu de chaque élément.
+// La lecture s'arrête au premier élément incorrect ou de taille inconnue
+fn children(mut data: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut elements = Vec::new();
+    while let Ok(Some((id, Some(size)))) = read_header(&mut data) {
+        if size > data.len() as u64 {
+            break;
+        }
+        let (content, rest) = data.split_at(size as usize);
+        elements.push((id, content));
+        data = rest;
+    }
+    elements
+}
+
+
+// Valeur d'un élément entier non signé (big-endian, sur 0 à 8 octets)
+fn unsigned(data: &[u8]) -> u64 {
+    data.iter().take(8).fold(0, |value, byte| value << 8 | *byte as u64)
+}
+
+
+// Valeur d'un élément flottant (sur 4 ou 8 octets)
+fn float(data: &[u8]) -> Option<f64> {
+    match data.len() {
+        4 => Some(f32::from_be_bytes([data[0], data[1], data[2], data[3]]) as f64),
+        8 => Some(f64::from_be_bytes([data[0], data[1], data[2], data[3], data[4], data[5], data[6], data[7]])),
+        _ => None,
+    }
+}
+
+
+// Valeur d'un élément texte (ASCII ou UTF-8, éventuellement complété par des octets nuls)
+fn text(data: &[u8]) -> String {
+    String::from_utf8_lossy(data).trim_end_matches('\0').trim().to_string()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::TemporaryFile;
+    use super::*;
+
+    // Élément EBML : identifiant, taille sur 8 octets, contenu
+    fn element(id: u32, content: &[u8]) -> Vec<u8> {
+        let id = id.to_be_bytes();
+        let start = id.iter().position(|byte| *byte != 0).unwrap();
+        [&id[start..], &[1], &(content.len() as u64).to_be_bytes()[1..], content].concat()
+    }
+
+    // Élément de taille inconnue (tous les bits de la taille valent 1)
+    fn unknown_size(id: u32) -> Vec<u8> {
+        [&id.to_be_bytes()[..], &[0x01, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]].concat()
+    }
+
+    fn track(track_type: u8, codec_id: &str, content: &[Vec<u8>]) -> Vec<u8> {
+        element(TRACK_ENTRY, &[&[element(TRACK_TYPE, &[track_type]), element(CODEC_ID, codec_id.as_bytes())], content].concat().concat())
+    }
+
+    // Fichier WebM : en-tête, segment de taille inconnue contenant un élément ignoré, Info, Tracks et un cluster
+    fn webm(duration: f64) -> Vec<u8> {
+        let info = element(INFO, &[
+            element(TIMESTAMP_SCALE, &[0x0f, 0x42, 0x40]),
+            element(DURATION, &duration.to_be_bytes()),
+            element(TITLE, b"Clip\0\0"),
+        ].concat());
+        let tracks = element(TRACKS, &[
+            track(AUDIO_TRACK as u8, "A_OPUS", &[]),
+            track(VIDEO_TRACK as u8, "V_VP9", &[
+                element(DEFAULT_DURATION, &33_366_667u32.to_be_bytes()),
+                element(VIDEO, &[element(PIXEL_WIDTH, &[0x07, 0x80]), element(PIXEL_HEIGHT, &[0x04, 0x38])].concat()),
+            ]),
+            track(VIDEO_TRACK as u8, "V_AV1", &[]),
+        ].concat());
+        [
+            element(EBML_HEADER, &element(DOC_TYPE, b"webm")),
+            unknown_size(SEGMENT),
+            element(0x114d_9b74, &[0; 32]),
+            info,
+            tracks,
+            unknown_size(0x1f43_b675),
+            vec![0; 64],
+        ].concat()
+    }
+
+    fn read(bytes: &[u8], name: &str) -> Result<VideoFile, MedmanError> {
+        let file = TemporaryFile::new(name, bytes);
+        let mut video_file = VideoFile::new(file.path());
+        read_matroska_metadata(&mut video_file).map(|_| video_file)
+    }
+
+    #[test]
+    fn reads_info_and_tracks() {
+        let video_file = read(&webm(2500.0), "read.webm").unwrap();
+        assert_eq!(video_file.title, "Clip");
+        assert_eq!(video_file.duration, Duration::from_millis(2500));
+        assert_eq!((video_file.width, video_file.height), (1920, 1080));
+        assert!((video_file.frame_rate - 29.97).abs() < 0.01);
+        assert_eq!((video_file.video_codec.as_str(), video_file.audio_codec.as_str()), ("vp9", "opus"));
+
+        // Durée hors limites : ignorée
+        for duration in [f64::MAX, -1.0, f64::NAN] {
+            assert_eq!(read(&webm(duration), "duration.webm").unwrap().duration, Duration::ZERO);
+        }
+    }
+
+    #[test]
+    fn truncated_or_invalid_files_are_errors() {
+        let bytes = webm(2500.0);
+        let info = bytes.windows(4).position(|window| window == INFO.to_be_bytes()).unwrap();
+        for length in [0, 3, 20, info - 10, info + 20] {
+            assert!(matches!(read(&bytes[..length], "truncated.webm"), Err(MedmanError::InvalidMedia(..))), "{}", length);
+        }
+        let avi = [element(EBML_HEADER, &element(DOC_TYPE, b"avi")), unknown_size(SEGMENT)].concat();
+        assert!(matches!(read(&avi, "avi.webm"), Err(MedmanError::InvalidMedia(..))));
+        // Identifiant de plus de 4 octets
+        assert!(read_header(&mut &[0x08, 0, 0, 0, 0, 0x80][..]).is_err());
+        assert!(children(&[0x42, 0x82, 0x88, b'w']).is_empty());
+    }
+}
@@ -0,0 +1,185 @@
+use std::fmt::Debug;
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+use serde::{Serialize, Deserialize};
+use crate::musicfile::MusicFile;
+use crate::photofile::PhotoFile;
+use crate::query::parse_date;
+use crate::search::{FieldType, SearchField};
+use crate::videofile::VideoFile;
+
+
+// Champs communs à tous les médias : chemin, taille et type de média
+pub const PATH: SearchField = SearchField::new("path", FieldType::Path).weighted(1);
+pub const SIZE: SearchField = SearchField::new("size", FieldType::Size);
+pub const KIND: SearchField = SearchField::new("kind", FieldType::Text);
+const COMMON_FIELDS: [SearchField; 3] = [PATH, SIZE, KIND];
+
+
+// Valeur d'un champ d'un média : textes (plusieurs pour un champ à valeurs multiples, comme les mots-clés)
+// ou nombre dans l'unité de stockage du champ (octets pour la taille, millisecondes pour la durée)
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldValue {
+    Text(Vec<String>),
+    Number(u64),
+}
+
+
+// Valeur stockée d'une date et heure au format ISO 8601 (2019-05-04T12:30:45) : le nombre décimal
+// AAAAMMJJhhmmss, qui conserve l'ordre chronologique. Renvoie None si la date est incorrecte
+pub fn date_value(date: &str) -> Option<u64> {
+    parse_date(date).ok().map(|(start, _)| start)
+}
+
+
+// Valeur stockée d'une coordonnée GPS en degrés (de -180 à 180) : en microdegrés, décalée de 180 degrés
+// pour que les coordonnées négatives (sud, ouest) soient représentées par des entiers positifs
+pub fn coordinate_value(degrees: f64) -> u64 {
+    ((degrees + 180.0) * 1_000_000.0).round().max(0.0) as u64
+}
+
+
+// Coordonnée GPS en degrés correspondant à une valeur stockée (inverse de coordinate_value)
+pub fn coordinate_degrees(value: u64) -> f64 {
+    value as f64 / 1_000_000.0 - 180.0
+}
+
+
+// Média d'une collection. La recherche, l'index et les statistiques n'accèdent aux médias qu'au travers
+// de cette interface : les champs sont lus par leur nom, tel qu'il est déclaré dans search_fields
+pub trait Media: Debug {
+    // Type de média, valeur du champ kind ("audio", "photo" ou "video")
+    fn kind(&self) -> &'static str;
+
+    // Chemin du fichier
+    fn path(&self) -> &Path;
+
+    // Taille du fichier en octets
+    fn size(&self) -> u64;
+
+    // Date de dernière modification du fichier lors de sa lecture
+    fn modified(&self) -> SystemTime;
+
+    // Durée du média (nulle pour un média qui n'en a pas)
+    fn duration(&self) -> Duration {
+        Duration::ZERO
+    }
+
+    // Valeur du champ 'name', ou None si ce type de média n'a pas ce champ
+    fn field(&self, name: &str) -> Option<FieldValue>;
+}
+
+
+// Valeur d'un champ commun à tous les médias (voir COMMON_FIELDS)
+pub fn common_field(media: &dyn Media, name: &str) -> Option<FieldValue> {
+    match name {
+        "path" => Some(FieldValue::Text(vec![media.path().to_string_lossy().into_owned()])),
+        "size" => Some(FieldValue::Number(media.size())),
+        "kind" => Some(FieldValue::Text(vec![media.kind().to_string()])),
+        _ => None,
+    }
+}
+
+
+// Champs de recherche de tous les types de médias : les champs communs, puis ceux de chaque type.
+// Un champ déclaré par plusieurs types de médias n'apparaît qu'une fois
+pub fn search_fields() -> &'static [SearchField] {
+    static FIELDS: OnceLock<Vec<SearchField>> = OnceLock::new();
+    FIELDS.get_or_init(|| {
+        let mut fields: Vec<SearchField> = Vec::new();
+        for field in COMMON_FIELDS.iter().chain(MusicFile::FIELDS.iter()).chain(PhotoFile::FIELDS.iter())
+            .chain(VideoFile::FIELDS.iter()) {
+            if !fields.iter().any(|known| known.name() == field.name()) {
+                fields.push(*field);
+            }
+        }
+        fields
+    })
+}
+
+
+// Média d'un catalogue, quel que soit son type. Il est sérialisé avec son type (champ "kind")
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "lowercase")]
+pub enum MediaFile {
+    Audio(MusicFile),
+    Photo(PhotoFile),
+    Video(VideoFile),
+}
+
+
+impl MediaFile {
+    // Le média en tant que Media, pour déléguer les fonctions de l'interface
+    fn media(&self) -> &dyn Media {
+        match self {
+            MediaFile::Audio(music_file) => music_file,
+            MediaFile::Photo(photo_file) => photo_file,
+            MediaFile::Video(video_file) => video_file,
+        }
+    }
+
+    // Le fichier audio, si le média en est un
+    pub fn as_audio(&self) -> Option<&MusicFile> {
+        match self {
+            MediaFile::Audio(music_file) => Some(music_file),
+            MediaFile::Photo(_) | MediaFile::Video(_) => None,
+        }
+    }
+
+    // Le fichier audio modifiable, si le média en est un
+    pub fn as_audio_mut(&mut self) -> Option<&mut MusicFile> {
+        match self {
+            MediaFile::Audio(music_file) => Some(music_file),
+            MediaFile::Photo(_) | MediaFile::Video(_) => None,
+        }
+    }
+}
+
+
+impl Media for MediaFile {
+    fn kind(&self) -> &'static str {
+        self.media().kind()
+    }
+
+    fn path(&self) -> &Path {
+        self.media().path()
+    }
+
+    fn size(&self) -> u64 {
+        self.media().size()
+    }
+
+    fn modified(&self) -> SystemTime {
+        self.media().modified()
+    }
+
+    fn duration(&self) -> Duration {
+        self.media().duration()
+    }
+
+    fn field(&self, name: &str) -> Option<FieldValue> {
+        self.media().field(name)
+    }
+}
+
+
+impl From<MusicFile> for MediaFile {
+    fn from(music_file: MusicFile) -> MediaFile {
+        MediaFile::Audio(music_file)
+    }
+}
+
+
+impl From<PhotoFile> for MediaFile {
+    fn from(photo_file: PhotoFile) -> MediaFile {
+        MediaFile::Photo(photo_file)
+    }
+}
+
+
+impl From<VideoFile> for MediaFile {
+    fn from(video_file: VideoFile) -> MediaFile {
+        MediaFile::Video(video_file)
+    }
+}
@@ -0,0 +1,474 @@
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::Duration;
+use mp3_metadata::Genre;
+use crate::error::MedmanError;
+use crate::musicfile::MusicFile;
+use crate::scan::get_media_genre;
+use crate::videofile::VideoFile;
+
+
+// Nom de l'atome "----" (iTunes) contenant les mots-clés d'un média
+const KEYWORDS_ATOM: &str = "KEYWORDS";
+
+
+// Itérateur sur les atomes contenus dans 'data' : type (4 octets) et contenu de chaque atome.
+// La taille d'un atome est sur 32 bits, ou sur 64 bits si elle vaut 1 ; une taille nulle désigne
+// un atome allant jusqu'à la fin des données. L'itération s'arrête au premier atome incorrect
+struct Atoms<'a> {
+    data: &'a [u8],
+}
+
+
+impl<'a> Iterator for Atoms<'a> {
+    type Item = (&'a [u8], &'a [u8]);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let data = self.data;
+        if data.len() < 8 {
+            return None;
+        }
+        let (size, header) = match u32::from_be_bytes(data[..4].try_into().unwrap()) {
+            0 => (data.len() as u64, 8),
+            1 if data.len() >= 16 => (u64::from_be_bytes(data[8..16].try_into().unwrap()), 16),
+            1 => return None,
+            size => (size as u64, 8),
+        };
+        if size < header as u64 || size > data.len() as u64 {
+            self.data = &[];
+            return None;
+        }
+        self.data = &data[size as usize..];
+        Some((&data[4..8], &data[header..size as usize]))
+    }
+}
+
+
+// Atomes contenus dans 'data'
+fn atoms(data: &[u8]) -> Atoms<'_> {
+    Atoms { data }
+}
+
+
+// Contenu du premier atome atteint en suivant le chemin 'path' (types des atomes imbriqués)
+fn find_atom<'a>(data: &'a [u8], path: &[&[u8]]) -> Option<&'a [u8]> {
+    path.iter().try_fold(data, |data, name| {
+        let content = atoms(data).find(|(kind, _)| kind == name).map(|(_, content)| content)?;
+        // L'atome meta (iTunes) commence par 4 octets de version et d'options avant ses atomes
+        match *name {
+            b"meta" if content.starts_with(&[0; 4]) => Some(&content[4..]),
+            _ => Some(content),
+        }
+    })
+}
+
+
+// Complète le média avec les métadonnées d'un fichier MP4 audio (AAC, ALAC) : durée lue dans l'atome
+// mvhd, fréquence d'échantillonnage lue dans la piste audio, tags lus dans les atomes de la liste ilst
+// (moov/udta/meta/ilst)
+pub(crate) fn read_mp4_metadata(music_file: &mut MusicFile) -> Result<(), MedmanError> {
+    let moov = read_file_moov(&music_file.path)?;
+    if let Some(duration) = movie_duration(&moov) {
+        music_file.duration = duration;
+    }
+    fill_audio_track(music_file, &moov);
+    if let Some(ilst) = find_atom(&moov, &[b"udta", b"meta", b"ilst"]) {
+        fill_ilst_tags(music_file, ilst);
+    }
+    Ok(())
+}
+
+
+// Complète la vidéo avec les métadonnées d'un fichier MP4 ou QuickTime (MOV) : durée lue dans l'atome mvhd,
+// dimensions, nombre d'images par seconde et codec lus dans la première piste vidéo, codec de la première
+// piste audio et titre lu dans la liste ilst (ou dans l'atome udta d'un fichier QuickTime)
+pub(crate) fn read_mp4_video_metadata(video_file: &mut VideoFile) -> Result<(), MedmanError> {
+    let moov = read_file_moov(&video_file.path)?;
+    if let Some(duration) = movie_duration(&moov) {
+        video_file.duration = duration;
+    }
+
+    if let Some(mdia) = find_track(&moov, b"vide") {
+        // Une entrée vidéo commence par 24 octets réservés ou prédéfinis, suivis de la largeur et de la hauteur
+        if let Some((format, entry)) = sample_entry(mdia) {
+            video_file.video_codec = codec_name(format);
+            if entry.len() >= 28 {
+                video_file.width = u16::from_be_bytes(entry[24..26].try_into().unwrap()) as u32;
+                video_file.height = u16::from_be_bytes(entry[26..28].try_into().unwrap()) as u32;
+            }
+        }
+        // Nombre d'images par seconde : nombre d'échantillons (atome stsz : version et options, taille
+        // commune des échantillons puis nombre d'échantillons) rapporté à la durée de la piste
+        let samples = find_atom(mdia, &[b"minf", b"stbl", b"stsz"])
+            .and_then(|stsz| stsz.get(8..12))
+            .map(|count| u32::from_be_bytes(count.try_into().unwrap()));
+        let time = find_atom(mdia, &[b"mdhd"]).and_then(time_header);
+        if let (Some(samples), Some((timescale, duration))) = (samples, time) {
+            if duration > 0 {
+                video_file.frame_rate = samples as f64 * timescale as f64 / duration as f64;
+            }
+        }
+    }
+    if let Some((format, _)) = find_track(&moov, b"soun").and_then(sample_entry) {
+        video_file.audio_codec = codec_name(format);
+    }
+
+    // Titre : tag ©nam de la liste ilst, ou atome ©nam de udta (QuickTime : taille du texte sur 16 bits,
+    // langue sur 16 bits, puis le texte)
+    let title = find_atom(&moov, &[b"udta", b"meta", b"ilst", b"\xa9nam"]).map(item_text)
+        .filter(|title| !title.is_empty())
+        .or_else(|| find_atom(&moov, &[b"udta", b"\xa9nam"])
+            .and_then(|name| name.get(4..))
+            .map(|title| String::from_utf8_lossy(title).trim_matches(|c: char| c == '\0' || c.is_whitespace()).to_string()));
+    if let Some(title) = title {
+        video_file.title = title;
+    }
+    Ok(())
+}
+
+
+// Lit le contenu de l'atome moov du fichier 'path'
+fn read_file_moov(path: &Path) -> Result<Vec<u8>, MedmanError> {
+    let error = |e: io::Error| match e.kind() {
+        io::ErrorKind::UnexpectedEof => MedmanError::InvalidMedia(path.to_path_buf(), "fichier MP4 tronqué".to_string()),
+        _ => MedmanError::Io(path.to_path_buf(), e),
+    };
+    let mut reader = BufReader::new(File::open(path).map_err(error)?);
+    match read_moov(&mut reader).map_err(error)? {
+        Some(moov) => Ok(moov),
+        None => Err(MedmanError::InvalidMedia(path.to_path_buf(), "atome moov absent".to_string())),
+    }
+}
+
+
+// Durée du film, lue dans l'atome mvhd
+fn movie_duration(moov: &[u8]) -> Option<Duration> {
+    let (timescale, duration) = find_atom(moov, &[b"mvhd"]).and_then(time_header)?;
+    let nanos = (duration as u128 * 1_000_000_000).checked_div(timescale as u128)?;
+    Some(Duration::from_nanos(nanos as u64))
+}
+
+
+// Échelle de temps et durée d'un atome mvhd ou mdhd : version (1 octet) et options, dates,
+// échelle de temps et durée (dates et durée sur 64 bits en version 1)
+fn time_header(header: &[u8]) -> Option<(u64, u64)> {
+    match header.first() {
+        Some(0) if header.len() >= 20 => Some((u32::from_be_bytes(header[12..16].try_into().unwrap()) as u64,
+            u32::from_be_bytes(header[16..20].try_into().unwrap()) as u64)),
+        Some(1) if header.len() >= 32 => Some((u32::from_be_bytes(header[20..24].try_into().unwrap()) as u64,
+            u64::from_be_bytes(header[24..32].try_into().unwrap()))),
+        _ => None,
+    }
+}
+
+
+// Contenu de l'atome mdia de la première piste dont le type de média (dans l'atome hdlr) est 'handler' :
+// "soun" pour une piste audio, "vide" pour une piste vidéo
+fn find_track<'a>(moov: &'a [u8], handler: &[u8]) -> Option<&'a [u8]> {
+    atoms(moov)
+        .filter(|(kind, _)| *kind == b"trak")
+        .filter_map(|(_, trak)| find_atom(trak, &[b"mdia"]))
+        .find(|mdia| find_atom(mdia, &[b"hdlr"]).is_some_and(|hdlr| hdlr.get(8..12) == Some(handler)))
+}
+
+
+// Première entrée de la description des échantillons d'une piste (atome stsd : version et options,
+// nombre d'entrées, puis les entrées) : format ("mp4a", "avc1"...) et contenu de l'entrée
+fn sample_entry(mdia: &[u8]) -> Option<(&[u8], &[u8])> {
+    find_atom(mdia, &[b"minf", b"stbl", b"stsd"])
+        .and_then(|stsd| stsd.get(8..))
+        .and_then(|entries| atoms(entries).next())
+}
+
+
+// Nom usuel du codec correspondant au format d'une entrée de description des échantillons
+// (le format lui-même, en minuscules, s'il n'est pas connu)
+fn codec_name(format: &[u8]) -> String {
+    match format {
+        b"avc1" | b"avc3" => "h264",
+        b"hvc1" | b"hev1" => "hevc",
+        b"av01" => "av1",
+        b"vp08" => "vp8",
+        b"vp09" => "vp9",
+        b"mp4v" => "mpeg4",
+        b"apch" | b"apcn" | b"apcs" | b"apco" | b"ap4h" | b"ap4x" => "prores",
+        b"jpeg" | b"mjpa" | b"mjpb" => "mjpeg",
+        b"mp4a" => "aac",
+        b"ac-3" => "ac3",
+        b"ec-3" => "eac3",
+        b".mp3" => "mp3",
+        b"Opus" => "opus",
+        b"fLaC" => "flac",
+        b"alac" => "alac",
+        b"lpcm" | b"sowt" | b"twos" | b"ipcm" | b"fpcm" => "pcm",
+        _ => return String::from_utf8_lossy(format).trim().to_lowercase(),
+    }.to_string()
+}
+
+
+// Lit le contenu de l'atome moov, en sautant les autres atomes du fichier (dont les données audio mdat).
+// Renvoie None si le fichier n'en contient pas
+fn read_moov<R: Read + Seek>(reader: &mut R) -> io::Result<Option<Vec<u8>>> {
+    loop {
+        let mut header = [0; 8];
+        match reader.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+        let (size, header_size) = match u32::from_be_bytes(header[..4].try_into().unwrap()) {
+            // Atome allant jusqu'à la fin du fichier
+            0 => {
+                let position = reader.stream_position()?;
+                (reader.seek(SeekFrom::End(0))? - position + 8, 8)
+            },
+            1 => {
+                let mut size = [0; 8];
+                reader.read_exact(&mut size)?;
+                (u64::from_be_bytes(size), 16)
+            },
+            size => (size as u64, 8),
+        };
+        if size < header_size {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "taille d'atome MP4 incorrecte"));
+        }
+        if &header[4..] == b"moov" {
+            let mut moov = Vec::new();
+            reader.take(size - header_size).read_to_end(&mut moov)?;
+            if (moov.len() as u64) < size - header_size {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            }
+            return Ok(Some(moov));
+        }
+        reader.seek(SeekFrom::Current((size - header_size) as i64))?;
+    }
+}
+
+
+// Complète le média avec la fréquence d'échantillonnage de la première piste audio (dont le type de média,
+// dans l'atome hdlr, est "soun") : c'est l'échelle de temps de la piste (atome mdhd), le champ de
+// la description de la piste étant limité à 65535 Hz. La résolution d'une piste ALAC est lue dans
+// la description de la piste (stsd), ainsi que le nombre de canaux
+fn fill_audio_track(music_file: &mut MusicFile, moov: &[u8]) {
+    let mdia = match find_track(moov, b"soun") {
+        Some(mdia) => mdia,
+        None => return,
+    };
+    if let Some((timescale, _)) = find_atom(mdia, &[b"mdhd"]).and_then(time_header) {
+        music_file.sample_rate = timescale as u32;
+    }
+    // Une entrée audio commence par 16 octets réservés, le nombre de canaux et la taille des échantillons
+    if let Some((format, entry)) = sample_entry(mdia).filter(|(_, entry)| entry.len() >= 20) {
+        music_file.channels = u16::from_be_bytes(entry[16..18].try_into().unwrap()) as u8;
+        if format == b"alac" {
+            music_file.bit_depth = u16::from_be_bytes(entry[18..20].try_into().unwrap()) as u8;
+        }
+    }
+}
+
+
+// Complète le média avec les tags de la liste ilst. Chaque tag est un atome contenant un atome data :
+// type de la valeur (1 pour de l'UTF-8, 2 pour de l'UTF-16, 0 ou 21 pour des données binaires),
+// langue, puis la valeur. Les tags absents ou vides conservent les valeurs déjà lues
+fn fill_ilst_tags(music_file: &mut MusicFile, ilst: &[u8]) {
+    for (kind, item) in atoms(ilst) {
+        let values = item_values(item);
+        let text = item_text(item);
+        // Nombre binaire : genre ID3v1 (décalé de 1) sur 16 bits, numéro de piste ou de disque
+        // sur les octets 2 et 3 (suivi du nombre total)
+        let number = |range: std::ops::Range<usize>| values.first()
+            .and_then(|(_, value)| value.get(range))
+            .map(|bytes| u16::from_be_bytes(bytes.try_into().unwrap()) as u32)
+            .filter(|number| *number != 0);
+        let field = match kind {
+            b"\xa9nam" => &mut music_file.title,
+            b"\xa9ART" => &mut music_file.author,
+            b"aART" => &mut music_file.album_artist,
+            b"\xa9alb" => &mut music_file.album,
+            b"\xa9wrt" => &mut music_file.composer,
+            b"\xa9cmt" => &mut music_file.comment,
+            b"\xa9gen" => &mut music_file.genre,
+            b"gnre" => {
+                if let Some(genre) = number(0..2).filter(|genre| *genre <= 256) {
+                    music_file.genre = get_media_genre(Genre::from((genre - 1) as u8));
+                }
+                continue;
+            },
+            b"\xa9day" => {
+                if let Some(year) = text.split(|c: char| !c.is_ascii_digit()).next().and_then(|year| year.parse().ok()) {
+                    music_file.year = year;
+                }
+                continue;
+            },
+            b"trkn" | b"disk" => {
+                if let Some(number) = number(2..4) {
+                    if kind == b"trkn" { music_file.track = number } else { music_file.disc = number }
+                }
+                continue;
+            },
+            // Atome libre (iTunes) : nom du tag dans l'atome name (précédé de 4 octets de version et d'options)
+            b"----" => {
+                let name = atoms(item).find(|(kind, _)| *kind == b"name").and_then(|(_, name)| name.get(4..));
+                if name.is_some_and(|name| name.eq_ignore_ascii_case(KEYWORDS_ATOM.as_bytes())) && !text.is_empty() {
+                    music_file.keywords = text.split(';')
+                        .map(str::trim)
+                        .filter(|keyword| !keyword.is_empty())
+                        .map(str::to_string)
+                        .collect();
+                }
+                continue;
+            },
+            _ => continue,
+        };
+        if !text.is_empty() {
+            *field = text;
+        }
+    }
+}
+
+
+// Valeurs d'un tag de la liste ilst (atomes data) : type de la valeur et valeur
+fn item_values(item: &[u8]) -> Vec<(u32, &[u8])> {
+    atoms(item)
+        .filter(|(kind, data)| *kind == b"data" && data.len() >= 8)
+        .map(|(_, data)| (u32::from_be_bytes(data[..4].try_into().unwrap()) & 0xff_ffff, &data[8..]))
+        .collect()
+}
+
+
+// Texte d'un tag de la liste ilst : ses valeurs texte non vides, séparées par des points-virgules
+fn item_text(item: &[u8]) -> String {
+    item_values(item).iter()
+        .filter_map(|(data_type, value)| match data_type {
+            1 => Some(String::from_utf8_lossy(value).trim().to_string()),
+            2 => Some(utf16_text(value).trim().to_string()),
+            _ => None,
+        })
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<String>>()
+        .join("; ")
+}
+
+
+// Décode un texte UTF-16 big-endian
+fn utf16_text(value: &[u8]) -> String {
+    let units: Vec<u16> = value.chunks_exact(2).map(|unit| u16::from_be_bytes([unit[0], unit[1]])).collect();
+    String::from_utf16_lossy(&units)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::TemporaryFile;
+    use super::*;
+
+    fn atom(kind: &[u8], content: &[u8]) -> Vec<u8> {
+        [&(8 + content.len() as u32).to_be_bytes(), kind, content].concat()
+    }
+
+    // Atome à version et options nulles
+    fn full_atom(kind: &[u8], content: &[u8]) -> Vec<u8> {
+        atom(kind, &[&[0; 4], content].concat())
+    }
+
+    // Atome mvhd ou mdhd (version 0) : dates nulles, échelle de temps et durée
+    fn time_atom(kind: &[u8], timescale: u32, duration: u32) -> Vec<u8> {
+        full_atom(kind, &[&[0; 8], &timescale.to_be_bytes()[..], &duration.to_be_bytes()].concat())
+    }
+
+    fn data(data_type: u32, value: &[u8]) -> Vec<u8> {
+        atom(b"data", &[&data_type.to_be_bytes(), &[0; 4], value].concat())
+    }
+
+    // Fichier M4A : piste ALAC stéréo de 24 bits à 96 kHz, durée de 2,5 s, tags ilst
+    fn m4a() -> Vec<u8> {
+        let entry = atom(b"alac", &[&[0; 16][..], &2u16.to_be_bytes(), &24u16.to_be_bytes(), &[0; 8]].concat());
+        let mdia = atom(b"mdia", &[
+            full_atom(b"hdlr", &[&[0; 4][..], b"soun", &[0; 12]].concat()),
+            time_atom(b"mdhd", 96000, 240000),
+            atom(b"minf", &atom(b"stbl", &full_atom(b"stsd", &[&1u32.to_be_bytes()[..], &entry].concat()))),
+        ].concat());
+        let ilst = atom(b"ilst", &[
+            atom(b"\xa9nam", &data(1, "Élégie".as_bytes())),
+            atom(b"\xa9ART", &[data(1, b"Artist A"), data(1, b""), data(1, b"Artist B")].concat()),
+            atom(b"\xa9alb", &data(2, &[0, b'L', 0, b'P'])),
+            atom(b"gnre", &data(0, &[0, 9])),
+            atom(b"\xa9day", &data(1, b"2004-06-01T00:00:00Z")),
+            atom(b"trkn", &data(0, &[0, 0, 0, 3, 0, 12, 0, 0])),
+            atom(b"----", &[full_atom(b"mean", b"com.apple.iTunes"), full_atom(b"name", b"keywords"), data(1, b"live; favori")].concat()),
+        ].concat());
+        let moov = atom(b"moov", &[
+            time_atom(b"mvhd", 1000, 2500),
+            atom(b"trak", &mdia),
+            atom(b"udta", &full_atom(b"meta", &[full_atom(b"hdlr", &[0; 20]), ilst].concat())),
+        ].concat());
+        [atom(b"ftyp", b"M4A \0\0\0\0"), atom(b"mdat", &[0; 64]), moov].concat()
+    }
+
+    fn read(bytes: &[u8], name: &str) -> Result<MusicFile, MedmanError> {
+        let file = TemporaryFile::new(name, bytes);
+        let mut music_file = MusicFile::new(file.path());
+        read_mp4_metadata(&mut music_file).map(|_| music_file)
+    }
+
+    #[test]
+    fn reads_audio_tracks_and_ilst_tags() {
+        let music_file = read(&m4a(), "read.m4a").unwrap();
+        assert_eq!(music_file.duration, Duration::from_millis(2500));
+        assert_eq!((music_file.sample_rate, music_file.channels, music_file.bit_depth), (96000, 2, 24));
+        assert_eq!(music_file.title, "Élégie");
+        assert_eq!(music_file.author, "Artist A; Artist B");
+        assert_eq!(music_file.album, "LP");
+        assert_eq!(music_file.genre, "Jazz");
+        assert_eq!((music_file.year, music_file.track), (2004, 3));
+        assert_eq!(music_file.keywords, ["live", "favori"]);
+    }
+
+    #[test]
+    fn reads_video_tracks() {
+        let entry = atom(b"avc1", &[&[0; 24][..], &1280u16.to_be_bytes(), &720u16.to_be_bytes(), &[0; 50]].concat());
+        let video = atom(b"trak", &atom(b"mdia", &[
+            full_atom(b"hdlr", &[&[0; 4][..], b"vide", &[0; 12]].concat()),
+            time_atom(b"mdhd", 30000, 60060),
+            atom(b"minf", &atom(b"stbl", &[
+                full_atom(b"stsd", &[&1u32.to_be_bytes()[..], &entry].concat()),
+                full_atom(b"stsz", &[&0u32.to_be_bytes()[..], &60u32.to_be_bytes()].concat()),
+            ].concat())),
+        ].concat()));
+        let audio = atom(b"trak", &atom(b"mdia", &[
+            full_atom(b"hdlr", &[&[0; 4][..], b"soun", &[0; 12]].concat()),
+            atom(b"minf", &atom(b"stbl", &full_atom(b"stsd", &[&1u32.to_be_bytes()[..], &atom(b"mp4a", &[0; 28])].concat()))),
+        ].concat()));
+        // Titre QuickTime : taille du texte, langue, texte
+        let moov = atom(b"moov", &[time_atom(b"mvhd", 600, 1201), audio, video, atom(b"udta", &atom(b"\xa9nam", b"\0\x04\0\0Clip"))].concat());
+        let file = TemporaryFile::new("read.mov", &[atom(b"ftyp", b"qt  "), moov, atom(b"mdat", &[])].concat());
+        let mut video_file = VideoFile::new(file.path());
+        read_mp4_video_metadata(&mut video_file).unwrap();
+        assert_eq!(video_file.duration, Duration::from_nanos(2_001_666_666));
+        assert_eq!((video_file.width, video_file.height), (1280, 720));
+        assert!((video_file.frame_rate - 29.97).abs() < 0.01);
+        assert_eq!((video_file.video_codec.as_str(), video_file.audio_codec.as_str()), ("h264", "aac"));
+        assert_eq!(video_file.title, "Clip");
+    }
+
+    #[test]
+    fn truncated_or_invalid_files_are_errors() {
+        let bytes = m4a();
+        let moov = bytes.len() - atoms(&bytes).last().unwrap().1.len() - 8;
+        for length in [moov + 4, moov + 8, moov + 100, bytes.len() - 1] {
+            assert!(matches!(read(&bytes[..length], "truncated.m4a"), Err(MedmanError::InvalidMedia(..))), "{}", length);
+        }
+        // Fichier sans atome moov, atome de taille inférieure à son en-tête
+        assert!(matches!(read(&atom(b"ftyp", b"M4A "), "no_moov.m4a"), Err(MedmanError::InvalidMedia(..))));
+        assert!(read(&[&4u32.to_be_bytes()[..], b"moov"].concat(), "invalid.m4a").is_err());
+    }
+
+    #[test]
+    fn atoms_stop_at_the_first_invalid_atom() {
+        let data = [atom(b"free", b"ab"), 100u32.to_be_bytes().to_vec(), b"skip".to_vec()].concat();
+        assert_eq!(atoms(&data).map(|(kind, _)| kind).collect::<Vec<_>>(), [b"free"]);
+        assert!(find_atom(&data, &[b"skip"]).is_none());
+    }
+}
@@ -1,9 +1,11 @@
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use serde::{Serialize, Deserialize};
+use crate::media::{common_field, FieldValue, Media};
+use crate::search::{FieldType, SearchField};
 
 
-// Structure de données pour le stockage des métadonnées d'un fichier mp3
+// Structure de données pour le stockage des métadonnées d'un fichier audio (mp3, FLAC, Ogg, MP4, WAV, AIFF)
 // Nous avons fait le choix de stocker ces données que nous trouvons pertinentes
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MusicFile {
@@ -15,11 +17,75 @@ pub struct MusicFile {
    pub album: String,
    pub year: u16,
    pub genre: String,
+   // Champs lus dans les tags ID3v2 (vides ou nuls s'ils sont absents)
+   #[serde(default)]
+   pub album_artist: String,
+   #[serde(default)]
+   pub track: u32,
+   #[serde(default)]
+   pub disc: u32,
+   #[serde(default)]
+   pub composer: String,
+   #[serde(default)]
+   pub comment: String,
+   // Mots-clés libres attribués par l'utilisateur (conservés dans le catalogue,
+   // et éventuellement dans une frame ID3v2 TXXX:KEYWORDS)
+   #[serde(default)]
+   pub keywords: Vec<String>,
+   // Propriétés du flux audio (nulles si elles sont inconnues)
+   #[serde(default)]
+   pub sample_rate: u32,
+   #[serde(default)]
+   pub bit_depth: u8,
+   #[serde(default)]
+   pub channels: u8,
+   // Débit moyen en kbit/s : moyenne des trames d'un fichier mp3, estimation à partir de la taille
+   // et de la durée du fichier pour les autres formats
+   #[serde(default)]
+   pub bitrate: u32,
+   // Débit variable (VBR) : les trames d'un fichier mp3 n'ont pas toutes le même débit
+   #[serde(default)]
+   pub vbr: bool,
+   // Version MPEG d'un fichier mp3 ("1", "2" ou "2.5"), vide pour les autres formats
+   #[serde(default)]
+   pub mpeg_version: String,
+   // Date de dernière modification du fichier, utilisée pour la réanalyse incrémentale
+   #[serde(default = "default_modified")]
+   pub modified: SystemTime,
+}
+
+
+// Date de modification par défaut (catalogues sauvegardés avant l'ajout du champ)
+fn default_modified() -> SystemTime {
+    UNIX_EPOCH
 }
 
 
 // Constructeur de la structure
 impl MusicFile {
+    // Champs de recherche propres aux fichiers audio. Les termes libres sont recherchés dans les champs
+    // ayant un poids, et les résultats classés selon le champ où ils ont été trouvés
+    pub const FIELDS: [SearchField; 18] = [
+        SearchField::new("title", FieldType::Text).weighted(5),
+        SearchField::new("author", FieldType::Text).weighted(4),
+        SearchField::new("duration", FieldType::Duration),
+        SearchField::new("album", FieldType::Text).weighted(3),
+        SearchField::new("year", FieldType::Number),
+        SearchField::new("genre", FieldType::Text).weighted(2),
+        SearchField::new("album_artist", FieldType::Text),
+        SearchField::new("track", FieldType::Number),
+        SearchField::new("disc", FieldType::Number),
+        SearchField::new("composer", FieldType::Text),
+        SearchField::new("comment", FieldType::Text),
+        SearchField::new("keyword", FieldType::Text).weighted(2).aliased(&["tag"]),
+        SearchField::new("bitrate", FieldType::Number),
+        SearchField::new("bitrate_mode", FieldType::Text),
+        SearchField::new("sample_rate", FieldType::Number),
+        SearchField::new("bit_depth", FieldType::Number),
+        SearchField::new("channels", FieldType::Number),
+        SearchField::new("mpeg_version", FieldType::Text),
+    ];
+
     pub fn new(path: &Path) -> MusicFile {
         // Initialisation des champs avec des valeurs 
         let music_file: MusicFile = MusicFile {
@@ -31,6 +97,19 @@ impl MusicFile {
             album: String::new(),
             year:0,
             genre: String::new(),
+            album_artist: String::new(),
+            track: 0,
+            disc: 0,
+            composer: String::new(),
+            comment: String::new(),
+            keywords: Vec::new(),
+            sample_rate: 0,
+            bit_depth: 0,
+            channels: 0,
+            bitrate: 0,
+            vbr: false,
+            mpeg_version: String::new(),
+            modified: UNIX_EPOCH,
         };
         music_file
     }
@@ -40,3 +119,55 @@ impl MusicFile {
         self.path.to_path_buf()
     }
 }
+
+
+impl Media for MusicFile {
+    fn kind(&self) -> &'static str {
+        "audio"
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn size(&self) -> u64 {
+        self.file_size
+    }
+
+    fn modified(&self) -> SystemTime {
+        self.modified
+    }
+
+    fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    // Valeur d'un champ de MusicFile::FIELDS (la durée en millisecondes) ou d'un champ commun.
+    // L'année, les numéros de piste et de disque et les propriétés du flux audio inconnus (nuls) n'ont pas de valeur
+    fn field(&self, name: &str) -> Option<FieldValue> {
+        let text = |value: &str| Some(FieldValue::Text(vec![value.to_string()]));
+        let known = |value: u64| (value > 0).then_some(FieldValue::Number(value));
+        match name {
+            "title" => text(&self.title),
+            "author" => text(&self.author),
+            "duration" => Some(FieldValue::Number(self.duration.as_millis() as u64)),
+            "album" => text(&self.album),
+            "year" => known(self.year as u64),
+            "genre" => text(&self.genre),
+            "album_artist" => text(&self.album_artist),
+            "track" => known(self.track as u64),
+            "disc" => known(self.disc as u64),
+            "composer" => text(&self.composer),
+            "comment" => text(&self.comment),
+            "keyword" => Some(FieldValue::Text(self.keywords.clone())),
+            "bitrate" => known(self.bitrate as u64),
+            "bitrate_mode" if self.mpeg_version.is_empty() => None,
+            "bitrate_mode" => text(if self.vbr { "vbr" } else { "cbr" }),
+            "sample_rate" => known(self.sample_rate as u64),
+            "bit_depth" => known(self.bit_depth as u64),
+            "channels" => known(self.channels as u64),
+            "mpeg_version" => text(&self.mpeg_version),
+            _ => common_field(self, name),
+        }
+    }
+}
@@ -0,0 +1,218 @@
+use std::convert::TryInto;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::time::Duration;
+use crate::error::MedmanError;
+use crate::musicfile::MusicFile;
+use crate::vorbis::VorbisComments;
+
+
+// Taille maximale d'une page Ogg (en-tête de 27 octets, table de 255 segments de 255 octets) :
+// la dernière page du fichier commence dans ce nombre d'octets avant la fin
+const MAX_PAGE_SIZE: u64 = 27 + 255 + 255 * 255;
+
+// Fréquence de la position (granule) des flux Opus, quelle que soit la fréquence d'origine
+const OPUS_GRANULE_RATE: u64 = 48000;
+
+
+// En-tête d'une page Ogg
+struct PageHeader {
+    serial: u32,
+    segments: Vec<u8>,
+}
+
+
+// Complète le média avec les métadonnées d'un fichier Ogg Vorbis ou Opus : fréquence d'échantillonnage
+// lue dans le paquet d'identification, tags lus dans le paquet de commentaires (commentaires Vorbis),
+// durée calculée à partir de la position (granule) de la dernière page du flux.
+// Seul le premier flux logique du fichier est lu
+pub(crate) fn read_ogg_metadata(music_file: &mut MusicFile) -> Result<(), MedmanError> {
+    let path = music_file.path.clone();
+    let error = |e: io::Error| match e.kind() {
+        io::ErrorKind::UnexpectedEof => MedmanError::InvalidMedia(path.clone(), "fichier Ogg tronqué".to_string()),
+        io::ErrorKind::InvalidData => MedmanError::InvalidMedia(path.clone(), e.to_string()),
+        _ => MedmanError::Io(path.clone(), e),
+    };
+    let mut reader = BufReader::new(File::open(&path).map_err(error)?);
+    let (serial, packets) = read_header_packets(&mut reader).map_err(error)?;
+    let (identification, comments) = match packets.as_slice() {
+        [identification, comments] => (identification, comments),
+        _ => return Err(MedmanError::InvalidMedia(path, "en-têtes Ogg absents".to_string())),
+    };
+
+    // Paquet d'identification : "\x01vorbis" ou "OpusHead", suivi des caractéristiques du flux
+    let (granule_rate, pre_skip, comments) = if identification.len() >= 16 && identification.starts_with(b"\x01vorbis") {
+        let sample_rate = u32::from_le_bytes(identification[12..16].try_into().unwrap());
+        music_file.sample_rate = sample_rate;
+        music_file.channels = identification[11];
+        (sample_rate as u64, 0, comments.strip_prefix(b"\x03vorbis"))
+    } else if identification.len() >= 16 && identification.starts_with(b"OpusHead") {
+        let pre_skip = u16::from_le_bytes(identification[10..12].try_into().unwrap());
+        // Fréquence du signal d'origine (informative, nulle si inconnue) : le flux est décodé à 48 kHz
+        let sample_rate = u32::from_le_bytes(identification[12..16].try_into().unwrap());
+        music_file.sample_rate = if sample_rate == 0 { OPUS_GRANULE_RATE as u32 } else { sample_rate };
+        music_file.channels = identification[9];
+        (OPUS_GRANULE_RATE, pre_skip as u64, comments.strip_prefix(b"OpusTags"))
+    } else {
+        return Err(MedmanError::InvalidMedia(path, "codec Ogg non supporté (Vorbis ou Opus attendu)".to_string()));
+    };
+    if let Some(comments) = comments.and_then(VorbisComments::parse) {
+        comments.fill(music_file);
+    }
+
+    if let Some(granule) = last_granule(&mut reader, serial).map_err(error)? {
+        let samples = granule.saturating_sub(pre_skip) as u128;
+        if let Some(nanos) = (samples * 1_000_000_000).checked_div(granule_rate as u128) {
+            music_file.duration = Duration::from_nanos(nanos as u64);
+        }
+    }
+    Ok(())
+}
+
+
+// Lit l'en-tête de la page suivante. Renvoie None à la fin du fichier
+fn read_page_header<R: Read>(reader: &mut R) -> io::Result<Option<PageHeader>> {
+    let mut header = [0; 27];
+    match reader.read_exact(&mut header) {
+        Ok(()) => (),
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    if &header[..4] != b"OggS" {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "page Ogg incorrecte"));
+    }
+    let serial = u32::from_le_bytes(header[14..18].try_into().unwrap());
+    // Table des segments : taille de chaque segment, un paquet se termine par un segment de moins de 255 octets
+    let mut segments = vec![0; header[26] as usize];
+    reader.read_exact(&mut segments)?;
+    Ok(Some(PageHeader { serial, segments }))
+}
+
+
+// Lit les deux premiers paquets (identification et commentaires) du premier flux logique,
+// qui peuvent s'étendre sur plusieurs pages. Renvoie le numéro de série du flux et les paquets lus
+fn read_header_packets<R: Read + Seek>(reader: &mut R) -> io::Result<(u32, Vec<Vec<u8>>)> {
+    let mut serial = None;
+    let mut packets: Vec<Vec<u8>> = Vec::new();
+    let mut packet: Vec<u8> = Vec::new();
+    while let Some(page) = read_page_header(reader)? {
+        let length: u64 = page.segments.iter().map(|size| *size as u64).sum();
+        // Les pages des autres flux logiques sont ignorées
+        if *serial.get_or_insert(page.serial) != page.serial {
+            reader.seek(SeekFrom::Current(length as i64))?;
+            continue;
+        }
+        for size in page.segments {
+            let start = packet.len();
+            packet.resize(start + size as usize, 0);
+            reader.read_exact(&mut packet[start..])?;
+            if size < 255 {
+                packets.push(std::mem::take(&mut packet));
+                if packets.len() == 2 {
+                    return Ok((page.serial, packets));
+                }
+            }
+        }
+    }
+    Ok((serial.unwrap_or(0), packets))
+}
+
+
+// Position (granule) de la dernière page du flux 'serial', lue dans la fin du fichier.
+// Renvoie None si elle est introuvable ou inconnue (-1)
+fn last_granule<R: Read + Seek>(reader: &mut R, serial: u32) -> io::Result<Option<u64>> {
+    let length = reader.seek(SeekFrom::End(0))?;
+    reader.seek(SeekFrom::Start(length.saturating_sub(MAX_PAGE_SIZE)))?;
+    let mut tail = Vec::new();
+    reader.read_to_end(&mut tail)?;
+    let granule = (0..tail.len().saturating_sub(27)).rev()
+        .filter(|i| &tail[*i..*i + 4] == b"OggS")
+        .filter(|i| u32::from_le_bytes(tail[*i + 14..*i + 18].try_into().unwrap()) == serial)
+        .map(|i| u64::from_le_bytes(tail[i + 6..i + 14].try_into().unwrap()))
+        .find(|granule| *granule != u64::MAX);
+    Ok(granule)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::TemporaryFile;
+    use super::*;
+
+    // Page Ogg contenant des paquets complets (somme de contrôle non calculée : elle n'est pas vérifiée)
+    fn page(serial: u32, granule: u64, packets: &[&[u8]]) -> Vec<u8> {
+        let mut segments = Vec::new();
+        for packet in packets {
+            segments.resize(segments.len() + packet.len() / 255, 255);
+            segments.push((packet.len() % 255) as u8);
+        }
+        let mut bytes = b"OggS\0\0".to_vec();
+        bytes.extend_from_slice(&granule.to_le_bytes());
+        bytes.extend_from_slice(&serial.to_le_bytes());
+        bytes.extend_from_slice(&[0; 8]);
+        bytes.push(segments.len() as u8);
+        bytes.extend_from_slice(&segments);
+        packets.iter().for_each(|packet| bytes.extend_from_slice(packet));
+        bytes
+    }
+
+    fn comments(prefix: &[u8], comments: &[&str]) -> Vec<u8> {
+        let comments = VorbisComments { vendor: "test".to_string(), comments: comments.iter().map(|c| c.to_string()).collect() };
+        [prefix, &comments.to_bytes()].concat()
+    }
+
+    // Fichier Ogg Vorbis stéréo à 44,1 kHz : en-têtes, page de données d'un autre flux, dernière page
+    fn vorbis(granule: u64) -> Vec<u8> {
+        let mut identification = b"\x01vorbis\0\0\0\0\x02".to_vec();
+        identification.extend_from_slice(&44100u32.to_le_bytes());
+        identification.resize(30, 0);
+        let comments = comments(b"\x03vorbis", &["TITLE=Blue in Green", "ARTIST=Bill Evans", "COMMENT=".repeat(40).as_str()]);
+        [
+            page(1, 0, &[&identification]),
+            page(1, 0, &[&comments, b"\x05vorbis"]),
+            page(2, 999_999, &[b"autre flux"]),
+            page(1, granule, &[b"audio"]),
+        ].concat()
+    }
+
+    fn read(bytes: &[u8], name: &str) -> Result<MusicFile, MedmanError> {
+        let file = TemporaryFile::new(name, bytes);
+        let mut music_file = MusicFile::new(file.path());
+        read_ogg_metadata(&mut music_file).map(|_| music_file)
+    }
+
+    #[test]
+    fn reads_vorbis_streams() {
+        let music_file = read(&vorbis(88200), "read.ogg").unwrap();
+        assert_eq!((music_file.sample_rate, music_file.channels), (44100, 2));
+        assert_eq!(music_file.duration, Duration::from_secs(2));
+        assert_eq!((music_file.title.as_str(), music_file.author.as_str()), ("Blue in Green", "Bill Evans"));
+    }
+
+    #[test]
+    fn reads_opus_streams() {
+        let mut identification = b"OpusHead\x01\x01".to_vec();
+        identification.extend_from_slice(&312u16.to_le_bytes());
+        identification.extend_from_slice(&0u32.to_le_bytes());
+        identification.extend_from_slice(&[0; 3]);
+        let bytes = [
+            page(7, 0, &[&identification]),
+            page(7, 0, &[&comments(b"OpusTags", &["TITLE=Opus"])]),
+            page(7, 48000 + 312, &[b"audio"]),
+        ].concat();
+        let music_file = read(&bytes, "read.opus").unwrap();
+        assert_eq!((music_file.sample_rate, music_file.channels), (48000, 1));
+        assert_eq!(music_file.duration, Duration::from_secs(1));
+        assert_eq!(music_file.title, "Opus");
+    }
+
+    #[test]
+    fn truncated_or_invalid_files_are_errors() {
+        let bytes = vorbis(88200);
+        for length in [0, 10, 40, 60] {
+            assert!(matches!(read(&bytes[..length], "truncated.ogg"), Err(MedmanError::InvalidMedia(..))), "{}", length);
+        }
+        assert!(matches!(read(b"RIFF0000WAVEfmt junk data", "invalid.ogg"), Err(MedmanError::InvalidMedia(..))));
+        assert!(matches!(read(&page(1, 0, &[b"\x01theora"]), "theora.ogg"), Err(MedmanError::InvalidMedia(..))));
+    }
+}
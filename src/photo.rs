@@ -0,0 +1,311 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom};
+use exif::{Exif, In, Tag, Value};
+use crate::error::MedmanError;
+use crate::media::date_value;
+use crate::photofile::PhotoFile;
+
+
+// Signatures des formats d'image supportés
+const PNG_SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+const JPEG_SIGNATURE: &[u8] = b"\xff\xd8";
+const TIFF_SIGNATURES: [&[u8]; 2] = [b"II*\0", b"MM\0*"];
+
+
+// Complète la photo avec la taille et la date de modification du fichier, les dimensions lues
+// dans l'en-tête de l'image (JPEG, PNG) et les métadonnées EXIF : appareil, objectif, date de prise de vue,
+// orientation et coordonnées GPS. Une photo sans données EXIF (ou dont les données EXIF sont illisibles)
+// conserve les informations de son en-tête
+pub(crate) fn read_photo_metadata(photo_file: &mut PhotoFile) -> Result<(), MedmanError> {
+    let path = photo_file.path.clone();
+    let error = |e: io::Error| match e.kind() {
+        io::ErrorKind::UnexpectedEof => MedmanError::InvalidMedia(path.clone(), "image tronquée".to_string()),
+        io::ErrorKind::InvalidData => MedmanError::InvalidMedia(path.clone(), e.to_string()),
+        _ => MedmanError::Io(path.clone(), e),
+    };
+    let meta = fs::metadata(&path).map_err(error)?;
+    photo_file.file_size = meta.len();
+    if let Ok(modified) = meta.modified() {
+        photo_file.modified = modified;
+    }
+
+    let mut reader = BufReader::new(File::open(&path).map_err(error)?);
+    if let Some((width, height)) = read_dimensions(&mut reader).map_err(error)? {
+        photo_file.width = width;
+        photo_file.height = height;
+    }
+    reader.seek(SeekFrom::Start(0)).map_err(error)?;
+    match exif::Reader::new().read_from_container(&mut reader) {
+        Ok(exif) => fill_exif(photo_file, &exif),
+        Err(exif::Error::Io(e)) => return Err(error(e)),
+        Err(_) => (),
+    }
+    Ok(())
+}
+
+
+// Lit les dimensions (largeur, hauteur) de l'image dans son en-tête. Renvoie None pour une image TIFF,
+// dont les dimensions sont lues avec les données EXIF
+fn read_dimensions<R: BufRead>(reader: &mut R) -> io::Result<Option<(u32, u32)>> {
+    let mut signature = [0; 8];
+    reader.read_exact(&mut signature[..4])?;
+    if TIFF_SIGNATURES.contains(&&signature[..4]) {
+        Ok(None)
+    } else if signature.starts_with(JPEG_SIGNATURE) {
+        read_jpeg_dimensions(reader, &signature[2..4])
+    } else {
+        reader.read_exact(&mut signature[4..])?;
+        if signature != PNG_SIGNATURE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "format d'image non reconnu"));
+        }
+        // Le premier chunk d'un fichier PNG est IHDR : largeur et hauteur en big-endian
+        let mut header = [0; 16];
+        reader.read_exact(&mut header)?;
+        if &header[4..8] != b"IHDR" {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "chunk IHDR absent"));
+        }
+        let width = u32::from_be_bytes([header[8], header[9], header[10], header[11]]);
+        let height = u32::from_be_bytes([header[12], header[13], header[14], header[15]]);
+        Ok(Some((width, height)))
+    }
+}
+
+
+// Lit les dimensions d'une image JPEG dans son segment SOF (Start Of Frame), en parcourant les segments
+// qui le précèdent. 'start' contient les octets déjà lus après la signature.
+// Renvoie None si les données de l'image (segment SOS) commencent avant tout segment SOF
+fn read_jpeg_dimensions<R: Read>(reader: &mut R, start: &[u8]) -> io::Result<Option<(u32, u32)>> {
+    let mut reader = start.chain(reader);
+    let mut byte = [0; 1];
+    loop {
+        // Un marqueur est un octet 0xFF, éventuellement répété, suivi du type du segment
+        reader.read_exact(&mut byte)?;
+        if byte[0] != 0xff {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "marqueur JPEG attendu"));
+        }
+        while byte[0] == 0xff {
+            reader.read_exact(&mut byte)?;
+        }
+        match byte[0] {
+            // Marqueurs sans données (RST0 à RST7, TEM, SOI)
+            0xd0..=0xd7 | 0x01 | 0xd8 => continue,
+            // Fin de l'image ou début des données compressées
+            0xd9 | 0xda => return Ok(None),
+            _ => (),
+        }
+        let mut length = [0; 2];
+        reader.read_exact(&mut length)?;
+        let length = u16::from_be_bytes(length) as u64;
+        if length < 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "segment JPEG incorrect"));
+        }
+        // Segments SOF0 à SOF15, hormis DHT (C4), JPG (C8) et DAC (CC) :
+        // précision, hauteur et largeur en big-endian
+        if (0xc0..=0xcf).contains(&byte[0]) && ![0xc4, 0xc8, 0xcc].contains(&byte[0]) {
+            let mut frame = [0; 5];
+            reader.read_exact(&mut frame)?;
+            let height = u16::from_be_bytes([frame[1], frame[2]]);
+            let width = u16::from_be_bytes([frame[3], frame[4]]);
+            return Ok(Some((width as u32, height as u32)));
+        }
+        let skipped = io::copy(&mut (&mut reader).take(length - 2), &mut io::sink())?;
+        if skipped < length - 2 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+}
+
+
+// Complète la photo avec les champs EXIF de l'image principale
+fn fill_exif(photo_file: &mut PhotoFile, exif: &Exif) {
+    photo_file.make = ascii_field(exif, Tag::Make);
+    photo_file.model = ascii_field(exif, Tag::Model);
+    photo_file.lens = ascii_field(exif, Tag::LensModel);
+
+    // Date de prise de vue, à défaut date de numérisation ou de dernière modification de l'image
+    photo_file.taken = [Tag::DateTimeOriginal, Tag::DateTimeDigitized, Tag::DateTime].iter()
+        .filter_map(|tag| date_field(exif, *tag))
+        .next()
+        .unwrap_or_default();
+
+    if let Some(orientation) = exif.get_field(Tag::Orientation, In::PRIMARY).and_then(|field| field.value.get_uint(0)) {
+        if (1..=8).contains(&orientation) {
+            photo_file.orientation = orientation as u8;
+        }
+    }
+
+    // Dimensions d'une image TIFF, ou d'une image dont l'en-tête ne les contient pas
+    if photo_file.width == 0 || photo_file.height == 0 {
+        for (width_tag, height_tag) in [(Tag::ImageWidth, Tag::ImageLength), (Tag::PixelXDimension, Tag::PixelYDimension)] {
+            let width = exif.get_field(width_tag, In::PRIMARY).and_then(|field| field.value.get_uint(0));
+            let height = exif.get_field(height_tag, In::PRIMARY).and_then(|field| field.value.get_uint(0));
+            if let (Some(width), Some(height)) = (width, height) {
+                photo_file.width = width;
+                photo_file.height = height;
+                break;
+            }
+        }
+    }
+
+    photo_file.latitude = gps_coordinate(exif, Tag::GPSLatitude, Tag::GPSLatitudeRef, b'S', 90.0);
+    photo_file.longitude = gps_coordinate(exif, Tag::GPSLongitude, Tag::GPSLongitudeRef, b'W', 180.0);
+}
+
+
+// Texte d'un champ EXIF ASCII, sans les caractères nuls et espaces de fin (vide si le champ est absent)
+fn ascii_field(exif: &Exif, tag: Tag) -> String {
+    match exif.get_field(tag, In::PRIMARY).map(|field| &field.value) {
+        Some(Value::Ascii(values)) => values.first()
+            .map(|value| String::from_utf8_lossy(value).trim_matches(|c: char| c == '\0' || c.is_whitespace()).to_string())
+            .unwrap_or_default(),
+        _ => String::new(),
+    }
+}
+
+
+// Date d'un champ EXIF ("2019:05:04 12:30:45") au format ISO 8601 (2019-05-04T12:30:45),
+// ou None si le champ est absent ou la date incorrecte
+fn date_field(exif: &Exif, tag: Tag) -> Option<String> {
+    let data = match exif.get_field(tag, In::PRIMARY).map(|field| &field.value) {
+        Some(Value::Ascii(values)) => values.first()?,
+        _ => return None,
+    };
+    let date = exif::DateTime::from_ascii(data).ok()?;
+    let date = format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+        date.year, date.month, date.day, date.hour, date.minute, date.second);
+    date_value(&date).map(|_| date)
+}
+
+
+// Coordonnée GPS en degrés décimaux d'un champ EXIF (degrés, minutes et secondes),
+// négative si le champ de référence vaut 'negative' (sud ou ouest). Une coordonnée supérieure
+// à 'max' degrés (90 pour la latitude, 180 pour la longitude) est incorrecte
+fn gps_coordinate(exif: &Exif, tag: Tag, ref_tag: Tag, negative: u8, max: f64) -> Option<f64> {
+    let degrees = match exif.get_field(tag, In::PRIMARY).map(|field| &field.value) {
+        Some(Value::Rational(values)) if values.len() >= 3 =>
+            values[0].to_f64() + values[1].to_f64() / 60.0 + values[2].to_f64() / 3600.0,
+        _ => return None,
+    };
+    if !degrees.is_finite() || degrees > max {
+        return None;
+    }
+    let is_negative = match exif.get_field(ref_tag, In::PRIMARY).map(|field| &field.value) {
+        Some(Value::Ascii(values)) => values.first().and_then(|value| value.first()) == Some(&negative),
+        _ => false,
+    };
+    Some(if is_negative { -degrees } else { degrees })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures::TemporaryFile;
+    use super::*;
+
+    // Entrée d'un répertoire (IFD) TIFF little-endian : tag, type, nombre de valeurs et valeurs encodées
+    type Entry = (u16, u16, u32, Vec<u8>);
+
+    fn ascii(tag: u16, text: &str) -> Entry {
+        (tag, 2, text.len() as u32 + 1, [text.as_bytes(), b"\0"].concat())
+    }
+
+    fn long(tag: u16, value: u32) -> Entry {
+        (tag, 4, 1, value.to_le_bytes().to_vec())
+    }
+
+    fn rationals(tag: u16, values: &[(u32, u32)]) -> Entry {
+        (tag, 5, values.len() as u32, values.iter().flat_map(|(n, d)| [n.to_le_bytes(), d.to_le_bytes()].concat()).collect())
+    }
+
+    // Répertoire situé à 'offset' : entrées triées, puis valeurs de plus de 4 octets
+    fn ifd(entries: &[Entry], offset: u32) -> Vec<u8> {
+        let mut entries = entries.to_vec();
+        entries.sort_by_key(|entry| entry.0);
+        let mut data_offset = offset + 2 + 12 * entries.len() as u32 + 4;
+        let (mut bytes, mut data) = ((entries.len() as u16).to_le_bytes().to_vec(), Vec::new());
+        for (tag, value_type, count, value) in &entries {
+            bytes.extend_from_slice(&[tag.to_le_bytes(), value_type.to_le_bytes()].concat());
+            bytes.extend_from_slice(&count.to_le_bytes());
+            if value.len() > 4 {
+                bytes.extend_from_slice(&data_offset.to_le_bytes());
+                data_offset += value.len() as u32;
+                data.extend_from_slice(value);
+            } else {
+                bytes.extend_from_slice(&[&value[..], &[0; 4][value.len()..]].concat());
+            }
+        }
+        [bytes, vec![0; 4], data].concat()
+    }
+
+    // Données TIFF : répertoire principal suivi des répertoires EXIF et GPS
+    fn tiff(main: &[Entry], exif: &[Entry], gps: &[Entry]) -> Vec<u8> {
+        let pointers = |exif_offset, gps_offset| [main, &[long(0x8769, exif_offset), long(0x8825, gps_offset)]].concat();
+        let exif_offset = 8 + ifd(&pointers(0, 0), 8).len() as u32;
+        let gps_offset = exif_offset + ifd(exif, exif_offset).len() as u32;
+        [b"II*\0\x08\0\0\0".to_vec(), ifd(&pointers(exif_offset, gps_offset), 8), ifd(exif, exif_offset), ifd(gps, gps_offset)].concat()
+    }
+
+    fn camera_exif(latitude: u32) -> Vec<u8> {
+        tiff(
+            &[ascii(0x010f, "Canon"), ascii(0x0110, "Canon EOS 5D Mark IV"), (0x0112, 3, 1, vec![6, 0])],
+            &[ascii(0x9003, "2019:05:04 12:30:45"), ascii(0xa434, "EF24-70mm f/2.8L II USM")],
+            &[ascii(1, "N"), rationals(2, &[(latitude, 1), (51, 1), (3024, 100)]), ascii(3, "W"), rationals(4, &[(2, 1), (17, 1), (4020, 100)])],
+        )
+    }
+
+    // Image JPEG : segment APP1 contenant les données EXIF, segment SOF0, données compressées
+    fn jpeg(width: u16, height: u16, exif: &[u8]) -> Vec<u8> {
+        let app1 = [&b"Exif\0\0"[..], exif].concat();
+        [
+            &b"\xff\xd8\xff\xe1"[..], &(app1.len() as u16 + 2).to_be_bytes(), &app1,
+            b"\xff\xc0\x00\x0b\x08", &height.to_be_bytes(), &width.to_be_bytes(), b"\x01\x01\x11\x00",
+            b"\xff\xda\x00\x08\x01\x01\x00\x00\x3f\x00", &[0; 10], b"\xff\xd9",
+        ].concat()
+    }
+
+    fn read(bytes: &[u8], name: &str) -> Result<PhotoFile, MedmanError> {
+        let file = TemporaryFile::new(name, bytes);
+        let mut photo_file = PhotoFile::new(file.path());
+        read_photo_metadata(&mut photo_file).map(|_| photo_file)
+    }
+
+    #[test]
+    fn reads_jpeg_dimensions_and_exif() {
+        let photo_file = read(&jpeg(4000, 3000, &camera_exif(48)), "read.jpg").unwrap();
+        assert_eq!((photo_file.width, photo_file.height, photo_file.orientation), (4000, 3000, 6));
+        assert_eq!((photo_file.make.as_str(), photo_file.model.as_str()), ("Canon", "Canon EOS 5D Mark IV"));
+        assert_eq!(photo_file.lens, "EF24-70mm f/2.8L II USM");
+        assert_eq!(photo_file.taken, "2019-05-04T12:30:45");
+        assert!((photo_file.latitude.unwrap() - 48.8584).abs() < 1e-6);
+        assert!((photo_file.longitude.unwrap() + 2.2945).abs() < 1e-6);
+
+        // Latitude supérieure à 90 degrés : coordonnée ignorée
+        let photo_file = read(&jpeg(4000, 3000, &camera_exif(91)), "gps.jpg").unwrap();
+        assert_eq!(photo_file.latitude, None);
+        assert!(photo_file.longitude.is_some());
+    }
+
+    #[test]
+    fn reads_png_and_tiff_dimensions() {
+        let png = [PNG_SIGNATURE, b"\0\0\0\x0dIHDR", &1920u32.to_be_bytes(), &1080u32.to_be_bytes(), &[8, 0, 0, 0, 0, 0, 0, 0, 0]].concat();
+        let photo_file = read(&png, "read.png").unwrap();
+        assert_eq!((photo_file.width, photo_file.height, photo_file.make.as_str()), (1920, 1080, ""));
+
+        let tiff = tiff(&[ascii(0x010f, "Epson"), long(0x0100, 2480), long(0x0101, 3508)], &[], &[]);
+        let photo_file = read(&tiff, "read.tif").unwrap();
+        assert_eq!((photo_file.width, photo_file.height, photo_file.make.as_str()), (2480, 3508, "Epson"));
+    }
+
+    #[test]
+    fn truncated_or_invalid_images_are_errors() {
+        let bytes = jpeg(4000, 3000, &camera_exif(48));
+        for length in [0, 3, 6, 40, bytes.len() - 30] {
+            assert!(matches!(read(&bytes[..length], "truncated.jpg"), Err(MedmanError::InvalidMedia(..))), "{}", length);
+        }
+        assert!(matches!(read(&PNG_SIGNATURE[..6], "truncated.png"), Err(MedmanError::InvalidMedia(..))));
+        assert!(matches!(read(b"not an image at all", "fake.png"), Err(MedmanError::InvalidMedia(..))));
+        assert!(matches!(read_jpeg_dimensions(&mut &b"\x00"[..], &[]), Err(e) if e.kind() == io::ErrorKind::InvalidData));
+        // Image sans segment SOF avant les données compressées
+        assert_eq!(read_jpeg_dimensions(&mut &b"\xff\xda"[..], &[]).unwrap(), None);
+    }
+}
@@ -0,0 +1,109 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use crate::media::{common_field, coordinate_value, date_value, FieldValue, Media};
+use crate::search::{FieldType, SearchField};
+
+
+// Structure de données pour le stockage des métadonnées d'une photo (JPEG, TIFF, PNG),
+// lues dans ses données EXIF et dans l'en-tête de l'image
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PhotoFile {
+   pub path: PathBuf,
+   pub file_size: u64,
+   // Date de prise de vue au format ISO 8601 (2019-05-04T12:30:45), vide si elle est inconnue
+   pub taken: String,
+   // Fabricant et modèle de l'appareil, et objectif utilisé
+   pub make: String,
+   pub model: String,
+   pub lens: String,
+   // Dimensions de l'image en pixels
+   pub width: u32,
+   pub height: u32,
+   // Orientation EXIF (1 à 8, 1 pour une image non tournée), nulle si elle est inconnue
+   pub orientation: u8,
+   // Coordonnées GPS du lieu de prise de vue, en degrés décimaux (négatifs au sud et à l'ouest)
+   pub latitude: Option<f64>,
+   pub longitude: Option<f64>,
+   // Date de dernière modification du fichier, utilisée pour la réanalyse incrémentale
+   pub modified: SystemTime,
+}
+
+
+impl PhotoFile {
+    // Champs de recherche propres aux photos
+    pub const FIELDS: [SearchField; 8] = [
+        SearchField::new("camera", FieldType::Text).weighted(4),
+        SearchField::new("lens", FieldType::Text).weighted(2),
+        SearchField::new("taken", FieldType::Date),
+        SearchField::new("width", FieldType::Number),
+        SearchField::new("height", FieldType::Number),
+        SearchField::new("orientation", FieldType::Number),
+        SearchField::new("latitude", FieldType::Coordinate),
+        SearchField::new("longitude", FieldType::Coordinate),
+    ];
+
+    pub fn new(path: &Path) -> PhotoFile {
+        PhotoFile {
+            path: path.to_path_buf(),
+            file_size: 0,
+            taken: String::new(),
+            make: String::new(),
+            model: String::new(),
+            lens: String::new(),
+            width: 0,
+            height: 0,
+            orientation: 0,
+            latitude: None,
+            longitude: None,
+            modified: UNIX_EPOCH,
+        }
+    }
+
+    // Appareil photo : le modèle, précédé du fabricant s'il ne le contient pas déjà
+    // ("Canon" et "Canon EOS 5D" donnent "Canon EOS 5D", "NIKON CORPORATION" et "D750" donnent
+    // "NIKON CORPORATION D750")
+    pub fn camera(&self) -> String {
+        let brand = self.make.split_whitespace().next().unwrap_or_default().to_lowercase();
+        if self.make.is_empty() || (!brand.is_empty() && self.model.to_lowercase().starts_with(&brand)) {
+            self.model.clone()
+        } else {
+            format!("{} {}", self.make, self.model).trim().to_string()
+        }
+    }
+}
+
+
+impl Media for PhotoFile {
+    fn kind(&self) -> &'static str {
+        "photo"
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn size(&self) -> u64 {
+        self.file_size
+    }
+
+    fn modified(&self) -> SystemTime {
+        self.modified
+    }
+
+    // Valeur d'un champ de PhotoFile::FIELDS ou d'un champ commun. La date de prise de vue et
+    // les coordonnées GPS n'ont pas de valeur lorsqu'elles sont inconnues
+    fn field(&self, name: &str) -> Option<FieldValue> {
+        match name {
+            "camera" => Some(FieldValue::Text(vec![self.camera()])),
+            "lens" => Some(FieldValue::Text(vec![self.lens.clone()])),
+            "taken" => date_value(&self.taken).map(FieldValue::Number),
+            "width" => Some(FieldValue::Number(self.width as u64)),
+            "height" => Some(FieldValue::Number(self.height as u64)),
+            "orientation" => Some(FieldValue::Number(self.orientation as u64)),
+            "latitude" => self.latitude.map(|latitude| FieldValue::Number(coordinate_value(latitude))),
+            "longitude" => self.longitude.map(|longitude| FieldValue::Number(coordinate_value(longitude))),
+            _ => common_field(self, name),
+        }
+    }
+}
@@ -0,0 +1,1086 @@
+use glob::Pattern;
+use regex::{Regex, RegexBuilder};
+use crate::index::tokenize as tokenize_text;
+use crate::media::coordinate_value;
+use crate::search::{normalize, str_to_search_field, strip_accents, FieldType, SearchField};
+
+
+// Distance d'édition maximale par défaut d'une recherche approchée (valeur~)
+pub const DEFAULT_MAX_EDITS: usize = 2;
+
+
+// Arbre syntaxique d'une requête de recherche.
+// La syntaxe est inspirée de celle d'Apache Lucene :
+//   beatles "abbey road"                termes libres, recherchés dans tous les champs texte
+//   title:Titre1 author:Auteur1         conjonction implicite (ET)
+//   author:"Miles Davis"                valeur contenant des espaces (échappements avec \)
+//   title:Titre1 AND author:Auteur1     conjonction explicite (aussi &&)
+//   title:Titre1 OR title:Titre2        disjonction (aussi ||)
+//   NOT genre:Rock                      négation (aussi !)
+//   +title:Titre1 -genre:Rock           clause obligatoire / interdite
+//   (genre:Rock OR genre:Pop) year:1970 regroupement avec des parenthèses
+//   year:[1990 TO 1999] year:{1990 TO *} intervalle inclusif / exclusif, * pour une borne ouverte
+//   year:>=2000 duration:<3min size:>5MB comparaisons sur les champs numériques
+//   taken:[2019 TO 2020-06] latitude:>48.5 dates (AAAA-MM-JJThh:mm:ss, à la précision voulue) et coordonnées
+//   title:love* author:?eatles          jokers (* : suite de caractères, ? : un caractère)
+//   path:"jazz/**/*.mp3"                motif glob, relatif au répertoire scanné
+//   title:=Yesterday                    mode exact (sensible à la casse et aux accents)
+//   title:yesterdy~ title:yesterdy~1    recherche approchée (distance d'édition maximale, 2 par défaut)
+//   title:love^2 (a OR b)^0.5           pondération du score d'une clause ou d'un groupe
+//   title:/lo+ve.*/                     expression régulière portant sur toute la valeur (sauf pour path,
+//                                       où une valeur commençant par / est un chemin absolu)
+// Les options sort:, limit: et offset: ne sont pas des conditions (voir SearchOptions) :
+//   sort:year desc,title asc            tri des résultats (score pour la pertinence)
+//   limit:50 offset:100                 pagination des résultats
+// Par défaut, les champs texte sont comparés sans tenir compte de la casse ni des accents.
+// Le ET est prioritaire sur le OU.
+#[derive(Debug, Clone)]
+pub enum Query {
+    Term(SearchField, Matcher),
+    // Terme libre, recherché dans les mots de tous les champs texte
+    FreeText(Matcher),
+    And(Vec<Query>),
+    Or(Vec<Query>),
+    Not(Box<Query>),
+    // Pondération du score d'une sous requête (clause^B ou (...)^B)
+    Boost(Box<Query>, f32),
+}
+
+
+// Condition portant sur la valeur d'un champ
+#[derive(Debug, Clone)]
+pub enum Matcher {
+    // Égalité avec la valeur normalisée (voir search::normalize) d'un champ texte
+    Text(String),
+    // Égalité stricte avec la valeur d'un champ texte
+    Exact(String),
+    // Motif avec jokers sur un champ texte ; le booléen indique le mode exact
+    Wildcard(Vec<WildcardToken>, bool),
+    // Motif glob sur le chemin relatif au répertoire scanné ; le booléen indique le mode exact
+    Glob(Pattern, bool),
+    // Recherche approchée : valeur et distance d'édition maximale ; le booléen indique le mode exact
+    Fuzzy(String, usize, bool),
+    // Expression régulière (ancrée sur toute la valeur du champ) ; le booléen indique le mode exact
+    Regex(Regex, bool),
+    // Intervalle semi-ouvert [min, max) sur la valeur d'un champ numérique
+    // (taille en octets, année, durée en millisecondes). Une borne max absente signifie +infini
+    Range(u64, Option<u64>),
+}
+
+
+// Options de présentation des résultats d'une requête : critères de tri (appliqués dans l'ordre,
+// les résultats étant classés par pertinence à défaut), nombre de résultats ignorés et nombre maximal de résultats
+#[derive(Debug, Clone, Default)]
+pub struct SearchOptions {
+    pub sort: Vec<SortKey>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+
+// Critère de tri des résultats : un champ (ou le score de pertinence) et le sens du tri
+#[derive(Debug, Clone)]
+pub struct SortKey {
+    pub field: SortField,
+    pub descending: bool,
+}
+
+
+// Valeur selon laquelle les résultats sont triés
+#[derive(Debug, Clone)]
+pub enum SortField {
+    Score,
+    Field(SearchField),
+}
+
+
+// Requête analysée : condition de recherche et options de présentation des résultats
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub query: Query,
+    pub options: SearchOptions,
+}
+
+
+// Noms des options d'une requête
+const OPTIONS: [&str; 3] = ["sort", "limit", "offset"];
+
+
+// Élément d'un motif avec jokers
+#[derive(Debug, Clone, PartialEq)]
+pub enum WildcardToken {
+    Char(char),
+    // ? : un caractère quelconque
+    AnyChar,
+    // * : une suite quelconque de caractères (éventuellement vide)
+    AnyString,
+}
+
+
+// Clause champ:valeur lue dans la requête
+#[derive(Debug, Clone, PartialEq)]
+struct RawClause {
+    field: String,
+    value: String,
+    // La valeur était entre guillemets
+    quoted: bool,
+    // La valeur était précédée de "=" (mode exact)
+    exact: bool,
+    // La valeur est une expression régulière (/.../)
+    regex: bool,
+    // Distance d'édition maximale d'une recherche approchée (valeur~N)
+    fuzzy: Option<usize>,
+    // Pondération de la clause (valeur^B)
+    boost: Option<f32>,
+}
+
+
+// Unités lexicales d'une requête
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Plus,
+    Minus,
+    Boost(f32),
+    Clause(RawClause),
+}
+
+
+// Découpe la requête en unités lexicales
+fn tokenize(request: &str) -> Result<Vec<Token>, String> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let chars: Vec<char> = request.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        // Parenthèses et préfixes d'une clause
+        let next_is_word = i + 1 < chars.len() && !chars[i + 1].is_whitespace();
+        match c {
+            '(' => { tokens.push(Token::LParen); i += 1; continue; },
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+                // Pondération d'un groupe : (...)^B
+                if i < chars.len() && chars[i] == '^' {
+                    let start = i + 1;
+                    i = start;
+                    while i < chars.len() && !chars[i].is_whitespace() && chars[i] != ')' {
+                        i += 1;
+                    }
+                    tokens.push(Token::Boost(parse_boost(&chars[start..i].iter().collect::<String>())?));
+                }
+                continue;
+            },
+            '+' if next_is_word => { tokens.push(Token::Plus); i += 1; continue; },
+            '-' if next_is_word => { tokens.push(Token::Minus); i += 1; continue; },
+            '!' if next_is_word => { tokens.push(Token::Not); i += 1; continue; },
+            _ => (),
+        }
+        // Lecture d'un mot (mot clé ou nom de champ) jusqu'au prochain espace, ":" ou parenthèse fermante
+        let start = i;
+        while i < chars.len() && !chars[i].is_whitespace() && chars[i] != ')' && chars[i] != ':' {
+            i += 1;
+        }
+        let word: String = chars[start..i].iter().collect();
+        if i < chars.len() && chars[i] == ':' && !word.is_empty() {
+            // Lecture de la valeur associée au champ, éventuellement précédée de "=" (mode exact)
+            i += 1;
+            let exact = i < chars.len() && chars[i] == '=';
+            if exact {
+                i += 1;
+            }
+            if word == "sort" && !exact {
+                let value = read_sort_value(&chars, &mut i);
+                tokens.push(Token::Clause(RawClause { field: word, value, quoted: false, exact, regex: false, fuzzy: None, boost: None }));
+                continue;
+            }
+            tokens.push(Token::Clause(read_clause(&chars, &mut i, word, exact)?));
+            continue;
+        }
+        match word.as_str() {
+            "AND" | "&&" => tokens.push(Token::And),
+            "OR" | "||" => tokens.push(Token::Or),
+            "NOT" => tokens.push(Token::Not),
+            // Terme libre, recherché dans tous les champs texte : il est relu depuis son début
+            // pour prendre en compte les guillemets et les échappements
+            _ => {
+                i = start;
+                tokens.push(Token::Clause(read_clause(&chars, &mut i, String::new(), false)?));
+            },
+        }
+    }
+    Ok(tokens)
+}
+
+
+// Lit la valeur de la clause portant sur le champ 'field' (vide pour un terme libre) à partir de la position 'i'
+fn read_clause(chars: &[char], i: &mut usize, field: String, exact: bool) -> Result<RawClause, String> {
+    // Une valeur commençant par / est une expression régulière, sauf pour le chemin
+    let regex = field != "path" && *i < chars.len() && chars[*i] == '/';
+    let (mut value, quoted) = if regex { (read_regex(chars, i)?, false) } else { read_value(chars, i)? };
+    let (fuzzy, boost) = read_suffixes(chars, i, &mut value, quoted || regex)?;
+    if regex && fuzzy.is_some() {
+        return Err(format!("Une expression régulière n'accepte pas la recherche approchée : /{}/", value));
+    }
+    if value.is_empty() {
+        return Err(format!("Valeur manquante pour le champ {}", field));
+    }
+    Ok(RawClause { field, value, quoted, exact, regex, fuzzy, boost })
+}
+
+
+// Lit la liste des critères de tri d'une option sort: à partir de la position 'i'.
+// Chaque champ peut être suivi de son sens de tri (asc ou desc) après un espace,
+// les critères étant séparés par des virgules : sort:year desc,title asc
+fn read_sort_value(chars: &[char], i: &mut usize) -> String {
+    let is_separator = |c: char| c.is_whitespace() || c == ',' || c == ')';
+    let skip_spaces = |mut j: usize| {
+        while j < chars.len() && chars[j].is_whitespace() {
+            j += 1;
+        }
+        j
+    };
+    let mut value = String::new();
+    loop {
+        // Nom du champ
+        while *i < chars.len() && !is_separator(chars[*i]) {
+            value.push(chars[*i]);
+            *i += 1;
+        }
+        // Sens du tri éventuel
+        let start = skip_spaces(*i);
+        let mut end = start;
+        while end < chars.len() && !is_separator(chars[end]) {
+            end += 1;
+        }
+        let direction: String = chars[start..end].iter().collect();
+        if direction.eq_ignore_ascii_case("asc") || direction.eq_ignore_ascii_case("desc") {
+            value.push(' ');
+            value.push_str(&direction);
+            *i = end;
+        }
+        // Critère suivant
+        let next = skip_spaces(*i);
+        if next < chars.len() && chars[next] == ',' {
+            value.push(',');
+            *i = skip_spaces(next + 1);
+        } else {
+            break;
+        }
+    }
+    value
+}
+
+
+// Lit la valeur d'une clause à partir de la position 'i' et avance 'i' jusqu'à la fin de la valeur.
+// La valeur peut être une phrase entre guillemets ("Miles Davis") ou un mot simple se terminant au
+// prochain espace ou à la prochaine parenthèse fermante. Dans les deux cas, le caractère \ permet
+// d'échapper le caractère suivant (\" \\ \  \) ...) ; \n et \t représentent un saut de ligne et une tabulation.
+// Les jokers échappés (\* \? \[ \] \~ \^) sont conservés avec leur \ pour être traités comme des caractères littéraux.
+// Un intervalle ([a TO b], {a TO b}) est lu tel quel jusqu'au crochet ou à l'accolade fermante.
+// Renvoie la valeur et un booléen indiquant si elle était entre guillemets
+fn read_value(chars: &[char], i: &mut usize) -> Result<(String, bool), String> {
+    if *i < chars.len() && (chars[*i] == '[' || chars[*i] == '{') {
+        let start = *i;
+        while *i < chars.len() && chars[*i] != ']' && chars[*i] != '}' {
+            *i += 1;
+        }
+        if *i == chars.len() {
+            return Err("Intervalle non fermé : ] ou } manquant".to_string());
+        }
+        *i += 1;
+        return Ok((chars[start..*i].iter().collect(), false));
+    }
+    let mut value = String::new();
+    let quoted = *i < chars.len() && chars[*i] == '"';
+    if quoted {
+        *i += 1;
+    }
+    loop {
+        match chars.get(*i) {
+            None if quoted => return Err(format!("Guillemet fermant manquant après \"{}", value)),
+            None => break,
+            Some('"') if quoted => {
+                *i += 1;
+                break;
+            },
+            Some(c) if !quoted && (c.is_whitespace() || *c == ')') => break,
+            Some('\\') => {
+                *i += 1;
+                match chars.get(*i) {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some(c) if "*?[]~^\\".contains(*c) => {
+                        value.push('\\');
+                        value.push(*c);
+                    },
+                    Some(c) => value.push(*c),
+                    None => return Err("Séquence d'échappement incomplète en fin de requête".to_string()),
+                }
+                *i += 1;
+            },
+            Some(c) => {
+                value.push(*c);
+                *i += 1;
+            },
+        }
+    }
+    Ok((value, quoted))
+}
+
+
+// Lit une expression régulière délimitée par des / à partir de la position 'i'.
+// Le caractère / peut être échappé (\/) ; les autres séquences \ sont transmises telles quelles
+fn read_regex(chars: &[char], i: &mut usize) -> Result<String, String> {
+    let mut regex = String::new();
+    *i += 1;
+    loop {
+        match chars.get(*i) {
+            None => return Err(format!("Expression régulière non terminée : /{}", regex)),
+            Some('/') => {
+                *i += 1;
+                return Ok(regex);
+            },
+            Some('\\') if chars.get(*i + 1) == Some(&'/') => {
+                regex.push('/');
+                *i += 2;
+            },
+            Some('\\') if *i + 1 < chars.len() => {
+                regex.push('\\');
+                regex.push(chars[*i + 1]);
+                *i += 2;
+            },
+            Some(c) => {
+                regex.push(*c);
+                *i += 1;
+            },
+        }
+    }
+}
+
+
+// Lit les suffixes d'une clause : ~ ou ~N (recherche approchée, renvoie la distance d'édition maximale)
+// puis ^B (pondération). Pour une valeur entre guillemets ou une expression régulière, les suffixes
+// suivent le délimiteur fermant ; sinon ils terminent la valeur lue et en sont retirés
+// (un ~ ou un ^ échappé avec \ reste un caractère littéral)
+fn read_suffixes(chars: &[char], i: &mut usize, value: &mut String, delimited: bool) -> Result<(Option<usize>, Option<f32>), String> {
+    let mut delimited_suffix = String::new();
+    let text = if delimited {
+        while *i < chars.len() && !chars[*i].is_whitespace() && chars[*i] != ')' {
+            delimited_suffix.push(chars[*i]);
+            *i += 1;
+        }
+        &mut delimited_suffix
+    } else {
+        value
+    };
+    let boost = match split_suffix(text, '^') {
+        Some(boost) => Some(parse_boost(&boost)?),
+        None => None,
+    };
+    let fuzzy = match split_suffix(text, '~') {
+        Some(distance) if distance.is_empty() => Some(DEFAULT_MAX_EDITS),
+        Some(distance) => Some(distance.parse::<usize>()
+            .map_err(|_| format!("Distance d'édition incorrecte : ~{}", distance))?),
+        None => None,
+    };
+    if delimited && !delimited_suffix.is_empty() {
+        return Err(format!("Suffixe incorrect : {}", delimited_suffix));
+    }
+    Ok((fuzzy, boost))
+}
+
+
+// Retire du texte la partie suivant la dernière occurrence non échappée de 'marker' et la renvoie
+fn split_suffix(text: &mut String, marker: char) -> Option<String> {
+    match text.rfind(marker) {
+        Some(position) if !text[..position].ends_with('\\') => {
+            let suffix = text[position + 1..].to_string();
+            text.truncate(position);
+            Some(suffix)
+        },
+        _ => None,
+    }
+}
+
+
+// Convertit la pondération d'une clause (suffixe ^B) en nombre strictement positif
+fn parse_boost(boost: &str) -> Result<f32, String> {
+    match boost.parse::<f32>() {
+        Ok(value) if value > 0.0 => Ok(value),
+        _ => Err(format!("Pondération incorrecte : ^{}", boost)),
+    }
+}
+
+
+// Analyseur syntaxique descendant récursif. Grammaire :
+//   ou    := et (OR et)*
+//   et    := unaire ((AND)? unaire)*
+//   unaire := NOT unaire | '+' unaire | '-' unaire | primaire
+//   primaire := '(' ou ')' ('^' B)? | champ:valeur | terme libre
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn parse_or(&mut self) -> Result<Query, String> {
+        let mut operands = vec![self.parse_and()?];
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            operands.push(self.parse_and()?);
+        }
+        Ok(if operands.len() == 1 { operands.remove(0) } else { Query::Or(operands) })
+    }
+
+    fn parse_and(&mut self) -> Result<Query, String> {
+        let mut operands = vec![self.parse_unary()?];
+        loop {
+            match self.peek() {
+                Some(Token::And) => {
+                    self.next();
+                    operands.push(self.parse_unary()?);
+                },
+                // Conjonction implicite entre deux clauses consécutives
+                Some(Token::Or) | Some(Token::RParen) | None => break,
+                Some(_) => operands.push(self.parse_unary()?),
+            }
+        }
+        Ok(if operands.len() == 1 { operands.remove(0) } else { Query::And(operands) })
+    }
+
+    fn parse_unary(&mut self) -> Result<Query, String> {
+        match self.peek() {
+            Some(Token::Not) | Some(Token::Minus) => {
+                self.next();
+                Ok(Query::Not(Box::new(self.parse_unary()?)))
+            },
+            Some(Token::Plus) => {
+                self.next();
+                self.parse_unary()
+            },
+            _ => self.parse_primary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Query, String> {
+        match self.next() {
+            Some(Token::LParen) => {
+                let query = self.parse_or()?;
+                match self.next() {
+                    Some(Token::RParen) => match self.peek() {
+                        Some(Token::Boost(boost)) => {
+                            let boost = *boost;
+                            self.next();
+                            Ok(Query::Boost(Box::new(query), boost))
+                        },
+                        _ => Ok(query),
+                    },
+                    _ => Err("Parenthèse fermante manquante".to_string()),
+                }
+            },
+            Some(Token::Clause(clause)) => parse_clause(clause),
+            Some(token) => Err(format!("Élément inattendu dans la requête : {:?}", token)),
+            None => Err("Fin de requête inattendue".to_string()),
+        }
+    }
+}
+
+
+// Construit le terme correspondant à une clause champ:valeur en vérifiant que le champ
+// existe et que la valeur est compatible avec son type
+fn parse_clause(clause: RawClause) -> Result<Query, String> {
+    let boost = clause.boost;
+    let query = parse_clause_value(clause)?;
+    Ok(match boost {
+        Some(boost) => Query::Boost(Box::new(query), boost),
+        None => query,
+    })
+}
+
+
+// Construit le terme correspondant à une clause, sans tenir compte de sa pondération
+fn parse_clause_value(clause: RawClause) -> Result<Query, String> {
+    let RawClause { field, value, quoted, exact, regex, fuzzy, .. } = clause;
+    if field.is_empty() {
+        return parse_free_text(&value, quoted, regex, fuzzy);
+    }
+    let search_field = match str_to_search_field(&field) {
+        Some(search_field) => search_field,
+        None => return Err(format!("Champ de recherche inconnu : {}", field)),
+    };
+    if search_field.is_numeric() && (regex || fuzzy.is_some()) {
+        return Err(format!("Le champ {} n'accepte pas les expressions régulières ni la recherche approchée", field));
+    }
+    if regex {
+        // Sauf en mode exact, l'expression est insensible à la casse et comparée à la valeur normalisée
+        let pattern = if exact { value.clone() } else { strip_accents(&value) };
+        let regex = RegexBuilder::new(&format!("^(?:{})$", pattern))
+            .case_insensitive(!exact)
+            .build()
+            .map_err(|e| format!("Expression régulière incorrecte pour le champ {} : {} ({})", field, value, e))?;
+        return Ok(Query::Term(search_field, Matcher::Regex(regex, exact)));
+    }
+    if let Some(distance) = fuzzy {
+        let value = if exact { unescape(&value) } else { normalize(&unescape(&value)) };
+        return Ok(Query::Term(search_field, Matcher::Fuzzy(value, distance, exact)));
+    }
+    if search_field.is_numeric() {
+        let matcher = parse_numeric_matcher(&search_field, &value, quoted)
+            .map_err(|e| format!("Valeur incorrecte pour le champ {} : {} ({})", field, value, e))?;
+        return Ok(Query::Term(search_field, matcher));
+    }
+    if !quoted && is_range_syntax(&value) {
+        return Err(format!("Le champ {} n'accepte pas les intervalles ni les comparaisons : {}", field, value));
+    }
+    let matcher = match search_field.field_type() {
+        // Motif glob sur le chemin
+        FieldType::Path if has_wildcards(&value, "*?[") => {
+            let pattern = if exact { value } else { normalize(&value) };
+            let pattern = Pattern::new(&to_glob(&pattern))
+                .map_err(|e| format!("Motif incorrect pour le champ {} : {} ({})", field, pattern, e))?;
+            Matcher::Glob(pattern, exact)
+        },
+        // Motif avec jokers
+        _ if has_wildcards(&value, "*?") => {
+            let pattern = if exact { value } else { normalize(&value) };
+            Matcher::Wildcard(parse_wildcard(&pattern), exact)
+        },
+        _ if exact => Matcher::Exact(unescape(&value)),
+        _ => Matcher::Text(normalize(&unescape(&value))),
+    };
+    Ok(Query::Term(search_field, matcher))
+}
+
+
+// Construit le terme libre correspondant à la valeur : la condition porte sur chacun des mots
+// des champs texte. Une valeur de plusieurs mots ("abbey road") est une phrase dont les mots
+// doivent se suivre dans un même champ
+fn parse_free_text(value: &str, quoted: bool, regex: bool, fuzzy: Option<usize>) -> Result<Query, String> {
+    let matcher = if regex {
+        let regex = RegexBuilder::new(&format!("^(?:{})$", strip_accents(value)))
+            .case_insensitive(true)
+            .build()
+            .map_err(|e| format!("Expression régulière incorrecte : {} ({})", value, e))?;
+        Matcher::Regex(regex, false)
+    } else if let Some(distance) = fuzzy {
+        Matcher::Fuzzy(normalize(&unescape(value)), distance, false)
+    } else if !quoted && has_wildcards(value, "*?") {
+        Matcher::Wildcard(parse_wildcard(&normalize(value)), false)
+    } else {
+        let text = normalize(&unescape(value));
+        if tokenize_text(&text).is_empty() {
+            return Err(format!("Terme de recherche sans mot : {}", value));
+        }
+        Matcher::Text(text)
+    };
+    Ok(Query::FreeText(matcher))
+}
+
+
+// Indique si la valeur contient l'un des jokers 'wildcards' non échappé
+fn has_wildcards(value: &str, wildcards: &str) -> bool {
+    let mut escaped = false;
+    for c in value.chars() {
+        if escaped {
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else if wildcards.contains(c) {
+            return true;
+        }
+    }
+    false
+}
+
+
+// Retire les \ des jokers échappés
+fn unescape(value: &str) -> String {
+    let mut result = String::new();
+    let mut escaped = false;
+    for c in value.chars() {
+        if c == '\\' && !escaped {
+            escaped = true;
+        } else {
+            result.push(c);
+            escaped = false;
+        }
+    }
+    result
+}
+
+
+// Découpe un motif avec jokers en éléments
+fn parse_wildcard(pattern: &str) -> Vec<WildcardToken> {
+    let mut tokens: Vec<WildcardToken> = Vec::new();
+    let mut escaped = false;
+    for c in pattern.chars() {
+        match c {
+            _ if escaped => {
+                tokens.push(WildcardToken::Char(c));
+                escaped = false;
+            },
+            '\\' => escaped = true,
+            '*' => tokens.push(WildcardToken::AnyString),
+            '?' => tokens.push(WildcardToken::AnyChar),
+            _ => tokens.push(WildcardToken::Char(c)),
+        }
+    }
+    tokens
+}
+
+
+// Convertit les jokers échappés avec \ en classes de caractères de la syntaxe glob ([*], [?], ...)
+fn to_glob(pattern: &str) -> String {
+    let mut result = String::new();
+    let mut escaped = false;
+    for c in pattern.chars() {
+        if escaped {
+            result.push_str(&Pattern::escape(&c.to_string()));
+            escaped = false;
+        } else if c == '\\' {
+            escaped = true;
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+
+// Indique si la valeur utilise la syntaxe des intervalles ou des comparaisons
+fn is_range_syntax(value: &str) -> bool {
+    value.starts_with('[') || value.starts_with('{') || value.starts_with('<') || value.starts_with('>')
+}
+
+
+// Convertit la valeur d'une clause portant sur un champ numérique en intervalle semi-ouvert [min, max).
+// Une valeur couvre un intervalle selon sa résolution (1 seconde pour les durées, la précision donnée
+// pour les dates et les coordonnées, 1 unité sinon) : l'égalité duration:2min45s correspond ainsi à toutes
+// les durées comprises entre 2min45s et 2min46s (exclu), et taken:2019 à toute l'année 2019
+fn parse_numeric_matcher(field: &SearchField, value: &str, quoted: bool) -> Result<Matcher, String> {
+    let parse = |text: &str| parse_numeric_value(field, unescape(text).trim());
+
+    // Intervalle non entre guillemets : [a TO b], {a TO b} ou un mélange des deux ; * désigne une borne ouverte
+    let low_bound = if quoted { None } else {
+        value.strip_prefix('[').map(|rest| (rest, true)).or_else(|| value.strip_prefix('{').map(|rest| (rest, false)))
+    };
+    if let Some((rest, low_inclusive)) = low_bound {
+        let (inner, high_inclusive) = match (rest.strip_suffix(']'), rest.strip_suffix('}')) {
+            (Some(inner), _) => (inner, true),
+            (_, Some(inner)) => (inner, false),
+            _ => return Err("intervalle attendu au format [min TO max]".to_string()),
+        };
+        let (low, high) = inner.split_once(" TO ")
+            .ok_or_else(|| "intervalle attendu au format [min TO max]".to_string())?;
+        let min = match low.trim() {
+            "*" => 0,
+            low if low_inclusive => parse(low)?.0,
+            low => parse(low)?.1,
+        };
+        let max = match high.trim() {
+            "*" => None,
+            high if high_inclusive => Some(parse(high)?.1),
+            high => Some(parse(high)?.0),
+        };
+        return Ok(Matcher::Range(min, max));
+    }
+
+    // Comparaison : <, <=, >, >= ou égalité
+    Ok(if let Some(rest) = value.strip_prefix(">=") {
+        Matcher::Range(parse(rest)?.0, None)
+    } else if let Some(rest) = value.strip_prefix("<=") {
+        Matcher::Range(0, Some(parse(rest)?.1))
+    } else if let Some(rest) = value.strip_prefix('>') {
+        Matcher::Range(parse(rest)?.1, None)
+    } else if let Some(rest) = value.strip_prefix('<') {
+        Matcher::Range(0, Some(parse(rest)?.0))
+    } else {
+        let (min, max) = parse(value)?;
+        Matcher::Range(min, Some(max))
+    })
+}
+
+
+// Convertit une valeur numérique en intervalle semi-ouvert [début, fin) dans l'unité de stockage
+// du champ : octets pour la taille, millisecondes pour la durée (voir media pour les dates et les coordonnées)
+fn parse_numeric_value(field: &SearchField, value: &str) -> Result<(u64, u64), String> {
+    let single = |value: u64| (value, value.saturating_add(1));
+    match field.field_type() {
+        FieldType::Size => parse_size(value).map(single),
+        FieldType::Duration => parse_duration::parse(value)
+            .map(|duration| duration.as_millis() as u64)
+            .map(|millis| (millis, millis.saturating_add(1000)))
+            .map_err(|_| "durée incorrecte".to_string()),
+        FieldType::Date => parse_date(value),
+        FieldType::Coordinate => parse_coordinate(value),
+        _ => value.parse::<u64>().map(single).map_err(|_| "entier positif attendu".to_string()),
+    }
+}
+
+
+// Convertit une date (2019, 2019-05, 2019-05-04, 2019-05-04T12:30 ou 2019-05-04T12:30:45) en intervalle
+// de dates stockées (voir media::date_value) couvrant la période désignée : l'année, le mois, le jour...
+pub fn parse_date(value: &str) -> Result<(u64, u64), String> {
+    // Bornes de l'année, du mois, du jour, de l'heure, des minutes et des secondes
+    const LIMITS: [(u64, u64); 6] = [(0, 9999), (1, 12), (1, 31), (0, 23), (0, 59), (0, 60)];
+    let error = || "date incorrecte (AAAA-MM-JJThh:mm:ss attendu)".to_string();
+    let parts: Vec<&str> = value.split(['-', 'T', ' ', ':']).collect();
+    if parts.len() > LIMITS.len() {
+        return Err(error());
+    }
+    let mut start = 0;
+    let mut unit = 1;
+    for (i, (part, (min, max))) in parts.iter().zip(LIMITS.iter()).enumerate() {
+        let number: u64 = Some(part)
+            .filter(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+            .and_then(|part| part.parse().ok())
+            .filter(|number| number >= min && number <= max)
+            .ok_or_else(error)?;
+        // Chaque composante occupe deux chiffres décimaux (quatre pour l'année) : AAAAMMJJhhmmss
+        unit = 10u64.pow(2 * (LIMITS.len() - 1 - i) as u32);
+        start += number * unit;
+    }
+    Ok((start, start + unit))
+}
+
+
+// Convertit une coordonnée GPS en degrés décimaux (48.8584, -2.29) en intervalle de coordonnées stockées
+// (voir media::coordinate_value) selon le nombre de décimales données (au plus 6) : 48.85 couvre
+// les coordonnées de 48.85 à 48.859999, -2.29 celles de -2.299999 à -2.29
+fn parse_coordinate(value: &str) -> Result<(u64, u64), String> {
+    let degrees: f64 = value.parse().ok()
+        .filter(|degrees: &f64| (-180.0..=180.0).contains(degrees))
+        .ok_or_else(|| "coordonnée en degrés décimaux attendue (entre -180 et 180)".to_string())?;
+    let decimals = value.split_once('.').map_or(0, |(_, decimals)| decimals.len()).min(6);
+    let unit = 10u64.pow((6 - decimals) as u32);
+    let value = coordinate_value(degrees);
+    Ok(if degrees.is_sign_negative() { ((value + 1).saturating_sub(unit), value + 1) } else { (value, value + unit) })
+}
+
+
+// Convertit une taille lisible (5MB, 1.5GiB, 700k, 1024) en nombre d'octets.
+// Les unités KB, MB, GB, TB sont décimales (puissances de 1000), KiB, MiB, GiB, TiB binaires
+// (puissances de 1024). La casse est ignorée et le "B" final est optionnel
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    let split = value.find(|c: char| !(c.is_ascii_digit() || c == '.')).unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let number: f64 = number.parse().map_err(|_| "taille incorrecte".to_string())?;
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1_000,
+        "m" | "mb" => 1_000_000,
+        "g" | "gb" => 1_000_000_000,
+        "t" | "tb" => 1_000_000_000_000,
+        "ki" | "kib" => 1 << 10,
+        "mi" | "mib" => 1 << 20,
+        "gi" | "gib" => 1 << 30,
+        "ti" | "tib" => 1 << 40,
+        _ => return Err(format!("unité de taille inconnue : {}", unit)),
+    };
+    Ok((number * multiplier as f64).round() as u64)
+}
+
+
+// Convertit la liste des critères d'une option sort: (year desc,title asc) en critères de tri.
+// Le tri est croissant par défaut, sauf pour le score de pertinence
+fn parse_sort(value: &str) -> Result<Vec<SortKey>, String> {
+    value.split(',')
+        .map(|criterion| {
+            let mut words = criterion.split_whitespace();
+            let name = words.next().ok_or_else(|| format!("Critère de tri manquant : sort:{}", value))?;
+            let field = match str_to_search_field(name) {
+                _ if name == "score" => SortField::Score,
+                Some(search_field) => SortField::Field(search_field),
+                None => return Err(format!("Champ de tri inconnu : {}", name)),
+            };
+            let descending = match words.next().map(|direction| direction.to_lowercase()) {
+                None => matches!(field, SortField::Score),
+                Some(direction) => direction == "desc",
+            };
+            Ok(SortKey { field, descending })
+        })
+        .collect()
+}
+
+
+// Applique aux options de la requête l'option 'clause' (sort:, limit: ou offset:)
+fn parse_option(options: &mut SearchOptions, clause: RawClause) -> Result<(), String> {
+    if clause.exact || clause.regex || clause.fuzzy.is_some() || clause.boost.is_some() {
+        return Err(format!("Valeur incorrecte pour l'option {} : {}", clause.field, clause.value));
+    }
+    let parse_count = |value: &str| value.parse::<usize>()
+        .map_err(|_| format!("Valeur incorrecte pour l'option {} : {} (entier positif attendu)", clause.field, value));
+    match clause.field.as_str() {
+        "sort" => options.sort = parse_sort(&clause.value)?,
+        "limit" => options.limit = Some(parse_count(&clause.value)?),
+        _ => options.offset = parse_count(&clause.value)?,
+    }
+    Ok(())
+}
+
+
+// Analyse la requête et renvoie l'arbre syntaxique correspondant, ainsi que ses options.
+// Les options doivent figurer au premier niveau de la requête (hors parenthèses et opérateurs) ;
+// une requête ne comportant que des options sélectionne tous les médias
+pub fn parse(request: &str) -> Result<Request, String> {
+    let mut tokens: Vec<Token> = Vec::new();
+    let mut options = SearchOptions::default();
+    let mut depth = 0;
+    for token in tokenize(request)? {
+        match token {
+            Token::LParen => depth += 1,
+            Token::RParen => depth -= 1,
+            Token::Clause(clause) if OPTIONS.contains(&clause.field.as_str()) => {
+                let after_operator = matches!(tokens.last(), Some(Token::And | Token::Or | Token::Not | Token::Plus | Token::Minus));
+                if depth > 0 || after_operator {
+                    return Err(format!("L'option {}: doit figurer hors des parenthèses et des opérateurs", clause.field));
+                }
+                parse_option(&mut options, clause)?;
+                continue;
+            },
+            _ => (),
+        }
+        tokens.push(token);
+    }
+    if tokens.is_empty() {
+        if options.sort.is_empty() && options.limit.is_none() && options.offset == 0 {
+            return Err("Requête vide".to_string());
+        }
+        return Ok(Request { query: Query::And(Vec::new()), options });
+    }
+    let mut parser = Parser { tokens, position: 0 };
+    let query = parser.parse_or()?;
+    match parser.peek() {
+        None => Ok(Request { query, options }),
+        Some(token) => Err(format!("Élément inattendu dans la requête : {:?}", token)),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::index::Index;
+    use crate::media::MediaFile;
+    use crate::musicfile::MusicFile;
+    use crate::search::matches;
+    use super::*;
+
+    // Forme lisible de l'arbre syntaxique : (a AND b), (a OR b), -a pour une négation, champ:valeur
+    // pour un terme, [min max) pour un intervalle
+    fn shape(query: &Query) -> String {
+        let join = |operands: &[Query], operator: &str| format!("({})",
+            operands.iter().map(shape).collect::<Vec<_>>().join(operator));
+        let matcher = |matcher: &Matcher| match matcher {
+            Matcher::Text(value) => value.clone(),
+            Matcher::Exact(value) => format!("={}", value),
+            Matcher::Wildcard(..) => "*".to_string(),
+            Matcher::Glob(pattern, _) => pattern.as_str().to_string(),
+            Matcher::Fuzzy(value, distance, _) => format!("{}~{}", value, distance),
+            Matcher::Regex(regex, _) => format!("/{}/", regex.as_str()),
+            Matcher::Range(min, max) => format!("[{} {})", min, max.map_or("*".to_string(), |max| max.to_string())),
+        };
+        match query {
+            Query::Term(field, value) => format!("{}:{}", field.name(), matcher(value)),
+            Query::FreeText(value) => matcher(value),
+            Query::And(operands) => join(operands, " AND "),
+            Query::Or(operands) => join(operands, " OR "),
+            Query::Not(operand) => format!("-{}", shape(operand)),
+            Query::Boost(operand, boost) => format!("{}^{}", shape(operand), boost),
+        }
+    }
+
+    fn parsed(request: &str) -> String {
+        shape(&parse(request).unwrap().query)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        assert_eq!(parsed("title:a title:b OR title:c"), "((title:a AND title:b) OR title:c)");
+        assert_eq!(parsed("title:a OR title:b AND title:c"), "(title:a OR (title:b AND title:c))");
+        assert_eq!(parsed("title:a || title:b && title:c"), "(title:a OR (title:b AND title:c))");
+        assert_eq!(parsed("(genre:Rock OR genre:Pop) year:1970"), "((genre:rock OR genre:pop) AND year:[1970 1971))");
+        assert!(parse("(title:a OR title:b").is_err());
+        assert!(parse("title:a)").is_err());
+    }
+
+    #[test]
+    fn prefixes_and_negations() {
+        assert_eq!(parsed("+title:a -title:b"), "(title:a AND -title:b)");
+        assert_eq!(parsed("!title:a NOT title:b"), "(-title:a AND -title:b)");
+        assert_eq!(parsed("-genre:Rock OR year:1970"), "(-genre:rock OR year:[1970 1971))");
+        assert_eq!(parsed("-(title:a OR title:b)"), "-(title:a OR title:b)");
+    }
+
+    #[test]
+    fn quoting_and_escapes() {
+        assert_eq!(parsed("author:\"Miles Davis\""), "author:miles davis");
+        assert_eq!(parsed("author:Miles\\ Davis"), "author:miles davis");
+        assert_eq!(parsed("title:\"say \\\"hi\\\"\""), "title:say \"hi\"");
+        assert_eq!(parsed("(title:\"a)b\" OR title:c\\))"), "(title:a)b OR title:c))");
+        assert_eq!(parsed("album:a\\tb"), "album:a\tb");
+        assert!(parse("author:\"Miles").is_err());
+        assert!(parse("title:a\\").is_err());
+    }
+
+    #[test]
+    fn ranges_and_comparisons() {
+        assert_eq!(parsed("year:[1990 TO 1999]"), "year:[1990 2000)");
+        assert_eq!(parsed("year:{1990 TO 1999}"), "year:[1991 1999)");
+        assert_eq!(parsed("year:[1990 TO *}"), "year:[1990 *)");
+        assert_eq!(parsed("year:{* TO 1999]"), "year:[0 2000)");
+        assert_eq!(parsed("year:>=2000"), "year:[2000 *)");
+        assert_eq!(parsed("year:>2000"), "year:[2001 *)");
+        assert_eq!(parsed("year:<2000"), "year:[0 2000)");
+        assert_eq!(parsed("year:<=2000"), "year:[0 2001)");
+        assert_eq!(parsed("year:1970"), "year:[1970 1971)");
+        assert_eq!(parsed("duration:2min45s"), "duration:[165000 166000)");
+        assert_eq!(parsed("size:>5MB"), "size:[5000001 *)");
+        assert!(parse("year:[1990 1999]").is_err());
+        assert!(parse("year:[1990 TO 1999").is_err());
+        assert!(parse("title:[a TO b]").is_err());
+    }
+
+    #[test]
+    fn malformed_ranges_are_errors() {
+        for request in ["year:\"[\"", "year:\\[", "size:\"{é\"", "year:{é}", "year:[]", "year:\"[1990 TO 1999]\""] {
+            assert!(parse(request).is_err(), "{}", request);
+        }
+    }
+
+    #[test]
+    fn wildcards_globs_and_exact_mode() {
+        assert_eq!(parsed("title:love*"), "title:*");
+        assert_eq!(parsed("author:?eatles"), "author:*");
+        assert_eq!(parsed("title:love\\*"), "title:love*");
+        assert_eq!(parsed("title:Élégie"), "title:elegie");
+        assert_eq!(parsed("title:=Élégie"), "title:=Élégie");
+        assert_eq!(parsed("path:\"jazz/**/*.mp3\""), "path:jazz/**/*.mp3");
+        assert_eq!(parsed("path:jazz/\\*/*.mp3"), "path:jazz/[*]/*.mp3");
+    }
+
+    #[test]
+    fn fuzzy_and_regex() {
+        assert_eq!(parsed("title:yesterdy~"), format!("title:yesterdy~{}", DEFAULT_MAX_EDITS));
+        assert_eq!(parsed("title:Yesterdy~1"), "title:yesterdy~1");
+        assert_eq!(parsed("title:\"let it b\"~"), format!("title:let it b~{}", DEFAULT_MAX_EDITS));
+        assert_eq!(parsed("title:yesterday\\~"), "title:yesterday~");
+        assert_eq!(parsed("title:/lo+ve.*/"), "title:/^(?:lo+ve.*)$/");
+        assert_eq!(parsed("title:/a\\/b c/"), "title:/^(?:a/b c)$/");
+        assert_eq!(parsed("path:/music/jazz"), "path:/music/jazz");
+        assert!(parse("title:/(/").is_err());
+        assert!(parse("year:/19.*/").is_err());
+        assert!(parse("year:1970~").is_err());
+    }
+
+    #[test]
+    fn free_text_terms() {
+        assert_eq!(parsed("beatles \"Abbey Road\""), "(beatles AND abbey road)");
+        assert_eq!(parsed("beatles OR title:help"), "(beatles OR title:help)");
+        assert_eq!(parsed("-live lov*"), "(-live AND *)");
+        assert_eq!(parsed("yesterdy~"), format!("yesterdy~{}", DEFAULT_MAX_EDITS));
+        assert!(parse("\"...\"").is_err());
+    }
+
+    #[test]
+    fn boosts() {
+        assert_eq!(parsed("title:love^2 beatles^0.5"), "(title:love^2 AND beatles^0.5)");
+        assert_eq!(parsed("(title:a OR title:b)^2 title:c"), "((title:a OR title:b)^2 AND title:c)");
+        assert_eq!(parsed("title:\"a b\"^3"), "title:a b^3");
+        assert_eq!(parsed("title:yesterdy~1^2"), "title:yesterdy~1^2");
+        assert!(matches!(parse("title:a\\^2").unwrap().query, Query::Term(_, Matcher::Text(value)) if value == "a^2"));
+        assert!(parse("title:love^x").is_err());
+        assert!(parse("title:love^-1").is_err());
+    }
+
+    #[test]
+    fn options() {
+        let request = parse("genre:Rock sort:year desc,title limit:50 offset:100").unwrap();
+        assert_eq!(shape(&request.query), "genre:rock");
+        let sort: Vec<(String, bool)> = request.options.sort.iter()
+            .map(|key| match &key.field {
+                SortField::Score => ("score".to_string(), key.descending),
+                SortField::Field(field) => (field.name().to_string(), key.descending),
+            })
+            .collect();
+        assert_eq!(sort, [("year".to_string(), true), ("title".to_string(), false)]);
+        assert_eq!(request.options.limit, Some(50));
+        assert_eq!(request.options.offset, 100);
+
+        let request = parse("sort:score limit:10").unwrap();
+        assert_eq!(shape(&request.query), "()");
+        assert!(request.options.sort[0].descending);
+
+        assert!(parse("(title:a sort:year)").is_err());
+        assert!(parse("title:a OR limit:5").is_err());
+        assert!(parse("limit:x").is_err());
+        assert!(parse("sort:unknown").is_err());
+    }
+
+    #[test]
+    fn invalid_clauses_are_errors() {
+        assert!(parse("").is_err());
+        assert!(parse("unknown:value").is_err());
+        assert!(parse("year:abc").is_err());
+        assert!(parse("title:").is_err());
+        assert!(parse("title:a AND").is_err());
+    }
+
+    #[test]
+    fn index_evaluation_matches_linear_scan() {
+        let root = Path::new("/music");
+        let music_file = |path: &str, title: &str, author: &str, genre: &str, year: u16, keywords: &[&str]| {
+            let mut music_file = MusicFile::new(&root.join(path));
+            music_file.title = title.to_string();
+            music_file.author = author.to_string();
+            music_file.genre = genre.to_string();
+            music_file.year = year;
+            music_file.keywords = keywords.iter().map(|keyword| keyword.to_string()).collect();
+            music_file.file_size = year as u64 * 1000;
+            MediaFile::from(music_file)
+        };
+        let files = [
+            music_file("jazz/so_what.mp3", "So What", "Miles Davis", "Jazz", 1959, &["modal"]),
+            music_file("jazz/impressions.mp3", "Impressions", "John Coltrane", "Jazz", 1963, &["live", "modal"]),
+            music_file("rock/yesterday.mp3", "Yesterday", "The Beatles", "Rock", 1965, &[]),
+            music_file("rock/abbey_road.mp3", "Come Together", "The Beatles", "Rock", 1969, &["favori"]),
+            music_file("pop/untagged.mp3", "Love Song", "", "", 0, &[]),
+            music_file("pop/électro.mp3", "Électricité", "Émilie", "Électro", 2021, &[]),
+        ];
+        let index = Index::build(root, &files);
+        let requests = [
+            "genre:jazz OR genre:rock", "genre:jazz -title:impressions", "year:[1960 TO 1969]", "year:<2000",
+            "year:>=2000", "year:{1959 TO *}", "size:>1.5MB", "title:love*", "author:*beat?es", "title:yesterdy~",
+            "title:/.*o.*/", "path:\"jazz/*\"", "genre:=Électro", "(genre:jazz OR title:yesterday) year:<1964",
+            "NOT author:\"the beatles\"", "year:0", "beatles", "\"miles davis\"", "electricite", "love* OR jazz",
+            "keyword:modal AND NOT author:\"Miles Davis\"", "tag:favori OR year:0", "genre:jazz -keyword:live", "modal", "kind:audio",
+        ];
+        for request in requests {
+            let query = parse(request).unwrap().query;
+            let linear: Vec<u32> = files.iter().enumerate()
+                .filter(|(_, media)| matches(&query, *media, root))
+                .map(|(doc, _)| doc as u32)
+                .collect();
+            assert_eq!(index.evaluate(&query, &files, root), linear, "{}", request);
+        }
+    }
+}
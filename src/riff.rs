@@ -0,0 +1,367 @@
+use std::convert::{TryFrom, TryInto};
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::time::Duration;
+use crate::error::MedmanError;
+use crate::musicfile::MusicFile;
+use crate::scan::fill_id3v2_tags;
+
+
+// Chunk d'un fichier RIFF (WAV) ou IFF (AIFF) : identifiant, taille et contenu (vide pour les chunks
+// qui ne sont pas lus, comme les données audio)
+struct Chunk {
+    id: [u8; 4],
+    size: u64,
+    data: Vec<u8>,
+}
+
+
+// Complète le média avec les métadonnées d'un fichier WAV : fréquence d'échantillonnage, nombre de canaux
+// et résolution lus dans le chunk "fmt ", durée calculée à partir de la taille du chunk "data",
+// tags lus dans le chunk LIST/INFO puis dans un chunk "id3 " (prioritaire)
+pub(crate) fn read_wav_metadata(music_file: &mut MusicFile) -> Result<(), MedmanError> {
+    let chunks = read_file_chunks(music_file, &[b"RIFF", b"RF64"], &[b"WAVE"], false, &[b"fmt ", b"LIST", b"ds64"])?;
+    let path = music_file.path.clone();
+    let format = match chunks.iter().find(|chunk| &chunk.id == b"fmt " && chunk.data.len() >= 16) {
+        Some(chunk) => &chunk.data,
+        None => return Err(MedmanError::InvalidMedia(path, "chunk fmt absent".to_string())),
+    };
+    // fmt : format, canaux, fréquence, débit en octets par seconde, alignement, résolution
+    music_file.channels = u16::from_le_bytes(format[2..4].try_into().unwrap()) as u8;
+    music_file.sample_rate = u32::from_le_bytes(format[4..8].try_into().unwrap());
+    music_file.bit_depth = u16::from_le_bytes(format[14..16].try_into().unwrap()) as u8;
+    let byte_rate = u32::from_le_bytes(format[8..12].try_into().unwrap());
+    // La taille des données d'un fichier RF64 (plus de 4 Go) est dans le chunk ds64
+    let ds64 = chunks.iter().find(|chunk| &chunk.id == b"ds64" && chunk.data.len() >= 16)
+        .map(|chunk| u64::from_le_bytes(chunk.data[8..16].try_into().unwrap()));
+    if let Some(size) = chunks.iter().find(|chunk| &chunk.id == b"data").map(|chunk| ds64.unwrap_or(chunk.size)) {
+        if let Some(nanos) = (size as u128 * 1_000_000_000).checked_div(byte_rate as u128) {
+            music_file.duration = Duration::from_nanos(nanos as u64);
+        }
+    }
+
+    fill_info_tags(music_file, &chunks);
+    fill_embedded_id3(music_file);
+    Ok(())
+}
+
+
+// Complète le média avec les métadonnées d'un fichier AIFF (ou AIFF-C) : nombre de canaux, nombre
+// d'échantillons, résolution et fréquence lus dans le chunk COMM, tags lus dans les chunks de texte
+// (NAME, AUTH, ANNO) puis dans un chunk "ID3 " (prioritaire)
+pub(crate) fn read_aiff_metadata(music_file: &mut MusicFile) -> Result<(), MedmanError> {
+    let chunks = read_file_chunks(music_file, &[b"FORM"], &[b"AIFF", b"AIFC"], true, &[b"COMM", b"NAME", b"AUTH", b"ANNO"])?;
+    let path = music_file.path.clone();
+    let common = match chunks.iter().find(|chunk| &chunk.id == b"COMM" && chunk.data.len() >= 18) {
+        Some(chunk) => &chunk.data,
+        None => return Err(MedmanError::InvalidMedia(path, "chunk COMM absent".to_string())),
+    };
+    // COMM : canaux, nombre d'échantillons par canal, résolution, fréquence (flottant de 80 bits)
+    music_file.channels = u16::from_be_bytes(common[0..2].try_into().unwrap()) as u8;
+    let frames = u32::from_be_bytes(common[2..6].try_into().unwrap());
+    music_file.bit_depth = u16::from_be_bytes(common[6..8].try_into().unwrap()) as u8;
+    let sample_rate = extended_to_f64(common[8..18].try_into().unwrap());
+    music_file.sample_rate = sample_rate.round() as u32;
+    if sample_rate >= 1.0 {
+        music_file.duration = Duration::from_secs_f64(frames as f64 / sample_rate);
+    }
+
+    fill_aiff_text_tags(music_file, &chunks);
+    fill_embedded_id3(music_file);
+    Ok(())
+}
+
+
+// Complète le média avec les tags du chunk LIST/INFO d'un fichier WAV : sous-chunks contenant chacun
+// un texte terminé par un caractère nul
+fn fill_info_tags(music_file: &mut MusicFile, chunks: &[Chunk]) {
+    for chunk in chunks.iter().filter(|chunk| &chunk.id == b"LIST" && chunk.data.starts_with(b"INFO")) {
+        for (id, value) in sub_chunks(&chunk.data[4..]) {
+            let value = decode_text(value);
+            if value.is_empty() {
+                continue;
+            }
+            match id {
+                b"INAM" => music_file.title = value,
+                b"IART" => music_file.author = value,
+                b"IPRD" => music_file.album = value,
+                b"IGNR" => music_file.genre = value,
+                b"ICMT" => music_file.comment = value,
+                b"ICRD" => music_file.year = leading_number(&value).and_then(|year| u16::try_from(year).ok()).unwrap_or(music_file.year),
+                b"ITRK" | b"IPRT" => music_file.track = leading_number(&value).unwrap_or(music_file.track),
+                b"IKEY" => music_file.keywords = value.split(';')
+                    .map(str::trim)
+                    .filter(|keyword| !keyword.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                _ => (),
+            }
+        }
+    }
+}
+
+
+// Complète le média avec les tags des chunks de texte d'un fichier AIFF (NAME, AUTH, ANNO)
+fn fill_aiff_text_tags(music_file: &mut MusicFile, chunks: &[Chunk]) {
+    for chunk in chunks {
+        let value = decode_text(&chunk.data);
+        if value.is_empty() {
+            continue;
+        }
+        match &chunk.id {
+            b"NAME" => music_file.title = value,
+            b"AUTH" => music_file.author = value,
+            b"ANNO" => music_file.comment = value,
+            _ => (),
+        }
+    }
+}
+
+
+// Vérifie que les tags d'un fichier WAV ou AIFF peuvent être écrits dans un chunk ID3. Le crate id3 ne
+// reconnaît que les en-têtes RIFF/WAVE et FORM : il corromprait un fichier RF64 en y ajoutant un en-tête ID3.
+// Les chunks de texte (LIST/INFO, NAME...) ne sont pas réécrits : un champ qui y a une valeur ne peut pas
+// être effacé, sa valeur réapparaîtrait à la prochaine analyse
+pub(crate) fn check_id3_chunk_writable(music_file: &MusicFile, aiff: bool) -> Result<(), MedmanError> {
+    let path = music_file.path.clone();
+    let chunks = if aiff {
+        read_file_chunks(music_file, &[b"FORM"], &[b"AIFF", b"AIFC"], true, &[b"NAME", b"AUTH", b"ANNO"])
+    } else {
+        read_file_chunks(music_file, &[b"RIFF"], &[b"WAVE"], false, &[b"LIST"])
+    };
+    let chunks = match chunks {
+        Ok(chunks) => chunks,
+        Err(MedmanError::InvalidMedia(..)) =>
+            return Err(MedmanError::TagWrite(path, "seuls les en-têtes RIFF et FORM sont supportés en écriture".to_string())),
+        Err(e) => return Err(e),
+    };
+    let mut stored = MusicFile::new(&path);
+    if aiff {
+        fill_aiff_text_tags(&mut stored, &chunks);
+    } else {
+        fill_info_tags(&mut stored, &chunks);
+    }
+    // Champ, valeur présente dans les chunks de texte, valeur à écrire
+    let fields = [
+        ("title", !stored.title.is_empty(), !music_file.title.is_empty()),
+        ("author", !stored.author.is_empty(), !music_file.author.is_empty()),
+        ("album", !stored.album.is_empty(), !music_file.album.is_empty()),
+        ("genre", !stored.genre.is_empty(), !music_file.genre.is_empty()),
+        ("comment", !stored.comment.is_empty(), !music_file.comment.is_empty()),
+        ("year", stored.year != 0, music_file.year != 0),
+        ("track", stored.track != 0, music_file.track != 0),
+        ("keyword", !stored.keywords.is_empty(), !music_file.keywords.is_empty()),
+    ];
+    match fields.iter().find(|(_, stored, written)| *stored && !*written) {
+        Some((name, ..)) => Err(MedmanError::TagWrite(path,
+            format!("le champ {} ne peut pas être effacé, sa valeur est aussi dans un chunk de texte", name))),
+        None => Ok(()),
+    }
+}
+
+
+// Ouvre le fichier et lit ses chunks, après avoir vérifié l'identifiant de l'en-tête (parmi 'forms')
+// et le type de fichier (parmi 'kinds'). Seul le contenu des chunks 'wanted' est lu
+fn read_file_chunks(music_file: &MusicFile, forms: &[&[u8]], kinds: &[&[u8]], big_endian: bool, wanted: &[&[u8]])
+    -> Result<Vec<Chunk>, MedmanError> {
+    let path = music_file.path.as_path();
+    let error = |e: io::Error| MedmanError::Io(path.to_path_buf(), e);
+    let mut reader = BufReader::new(File::open(path).map_err(error)?);
+    let mut header = [0; 12];
+    if reader.read_exact(&mut header).is_err() || !forms.contains(&&header[..4]) || !kinds.contains(&&header[8..]) {
+        return Err(MedmanError::InvalidMedia(path.to_path_buf(), "en-tête incorrect".to_string()));
+    }
+    read_chunks(&mut reader, big_endian, wanted).map_err(error)
+}
+
+
+// Lit les chunks qui suivent l'en-tête, jusqu'à la fin du fichier. La taille des chunks est en
+// little-endian (RIFF) ou en big-endian (IFF) ; un chunk de taille impaire est suivi d'un octet nul
+fn read_chunks<R: Read + Seek>(reader: &mut R, big_endian: bool, wanted: &[&[u8]]) -> io::Result<Vec<Chunk>> {
+    let mut chunks = Vec::new();
+    loop {
+        let mut header = [0; 8];
+        match reader.read_exact(&mut header) {
+            Ok(()) => (),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(chunks),
+            Err(e) => return Err(e),
+        }
+        let size = header[4..].try_into().unwrap();
+        let size = if big_endian { u32::from_be_bytes(size) } else { u32::from_le_bytes(size) } as u64;
+        let id: [u8; 4] = header[..4].try_into().unwrap();
+        let mut data = Vec::new();
+        if wanted.contains(&&id[..]) {
+            // Un fichier tronqué conserve le début du chunk
+            reader.take(size).read_to_end(&mut data)?;
+            reader.seek(SeekFrom::Current((size % 2) as i64))?;
+        } else {
+            reader.seek(SeekFrom::Current((size + size % 2) as i64))?;
+        }
+        chunks.push(Chunk { id, size, data });
+    }
+}
+
+
+// Sous-chunks (RIFF, little-endian) contenus dans 'data' : identifiant et contenu
+fn sub_chunks(mut data: &[u8]) -> Vec<(&[u8], &[u8])> {
+    let mut chunks = Vec::new();
+    while data.len() >= 8 {
+        let size = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+        let end = (8 + size).min(data.len());
+        chunks.push((&data[..4], &data[8..end]));
+        data = &data[(end + size % 2).min(data.len())..];
+    }
+    chunks
+}
+
+
+// Complète le média avec le tag ID3v2 contenu dans un chunk "id3 " du fichier, s'il existe
+fn fill_embedded_id3(music_file: &mut MusicFile) {
+    if let Ok(tag) = id3::Tag::read_from_path(&music_file.path) {
+        fill_id3v2_tags(music_file, &tag);
+    }
+}
+
+
+// Décode un texte de chunk : UTF-8, ou à défaut Latin-1, sans les caractères nuls et espaces de fin
+fn decode_text(value: &[u8]) -> String {
+    let text = match std::str::from_utf8(value) {
+        Ok(text) => text.to_string(),
+        Err(_) => value.iter().map(|byte| *byte as char).collect(),
+    };
+    text.trim_end_matches(char::from(0)).trim().to_string()
+}
+
+
+// Nombre en tête d'un texte ("2019-05-04" pour une date, "3/12" pour un numéro de piste)
+fn leading_number(value: &str) -> Option<u32> {
+    value.split(|c: char| !c.is_ascii_digit()).next().and_then(|digits| digits.parse().ok())
+}
+
+
+// Convertit un flottant IEEE 754 de 80 bits (big-endian) : signe et exposant sur 16 bits,
+// mantisse de 64 bits avec bit entier explicite
+fn extended_to_f64(bytes: [u8; 10]) -> f64 {
+    let exponent = (u16::from_be_bytes([bytes[0], bytes[1]]) & 0x7fff) as i32;
+    let mantissa = u64::from_be_bytes(bytes[2..10].try_into().unwrap());
+    if exponent == 0 && mantissa == 0 {
+        return 0.0;
+    }
+    let value = mantissa as f64 * 2f64.powi(exponent - 16383 - 63);
+    if bytes[0] & 0x80 != 0 { -value } else { value }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use crate::fixtures::TemporaryFile;
+    use crate::tag::write_tags;
+    use super::*;
+
+    // Chunk RIFF (little-endian) ou IFF (big-endian), suivi d'un octet nul si sa taille est impaire
+    fn chunk(id: &[u8], data: &[u8], big_endian: bool) -> Vec<u8> {
+        let size = data.len() as u32;
+        let size = if big_endian { size.to_be_bytes() } else { size.to_le_bytes() };
+        let padding: &[u8] = if data.len() % 2 == 1 { &[0] } else { &[] };
+        [id, &size, data, padding].concat()
+    }
+
+    fn riff(form: &[u8], kind: &[u8], chunks: &[Vec<u8>]) -> Vec<u8> {
+        let chunks = chunks.concat();
+        [form, &(4 + chunks.len() as u32).to_le_bytes(), kind, &chunks].concat()
+    }
+
+    // Chunk fmt : PCM stéréo de 16 bits à 44,1 kHz
+    fn format() -> Vec<u8> {
+        let data = [&1u16.to_le_bytes()[..], &2u16.to_le_bytes(), &44100u32.to_le_bytes(), &176400u32.to_le_bytes(),
+            &4u16.to_le_bytes(), &16u16.to_le_bytes()].concat();
+        chunk(b"fmt ", &data, false)
+    }
+
+    fn info(entries: &[(&[u8], &str)]) -> Vec<u8> {
+        let entries: Vec<u8> = entries.iter().flat_map(|(id, value)| chunk(id, &[value.as_bytes(), b"\0"].concat(), false)).collect();
+        chunk(b"LIST", &[&b"INFO"[..], &entries].concat(), false)
+    }
+
+    fn wav(info_entries: &[(&[u8], &str)]) -> Vec<u8> {
+        riff(b"RIFF", b"WAVE", &[format(), info(info_entries), chunk(b"data", &[0; 352800], false)])
+    }
+
+    fn read(path: &Path, aiff: bool) -> Result<MusicFile, MedmanError> {
+        let mut music_file = MusicFile::new(path);
+        if aiff { read_aiff_metadata(&mut music_file) } else { read_wav_metadata(&mut music_file) }.map(|_| music_file)
+    }
+
+    #[test]
+    fn reads_wav_and_rf64_files() {
+        let file = TemporaryFile::new("read.wav", &wav(&[(b"INAM", "Oiseaux"), (b"IART", "Field Team"), (b"ICRD", "2019-05-04"), (b"IKEY", "nature; aube")]));
+        let music_file = read(file.path(), false).unwrap();
+        assert_eq!((music_file.sample_rate, music_file.channels, music_file.bit_depth), (44100, 2, 16));
+        assert_eq!(music_file.duration, Duration::from_secs(2));
+        assert_eq!((music_file.title.as_str(), music_file.author.as_str(), music_file.year), ("Oiseaux", "Field Team", 2019));
+        assert_eq!(music_file.keywords, ["nature", "aube"]);
+
+        // RF64 : la taille des données est dans le chunk ds64, celle du chunk data vaut 0xffffffff
+        let ds64 = [&0u64.to_le_bytes()[..], &(176400u64 * 3).to_le_bytes(), &0u64.to_le_bytes(), &0u32.to_le_bytes()].concat();
+        let bytes = [riff(b"RF64", b"WAVE", &[chunk(b"ds64", &ds64, false), format()]), b"data\xff\xff\xff\xff".to_vec()].concat();
+        let file = TemporaryFile::new("read_rf64.wav", &bytes);
+        assert_eq!(read(file.path(), false).unwrap().duration, Duration::from_secs(3));
+    }
+
+    #[test]
+    fn reads_aiff_files() {
+        let common = [&1u16.to_be_bytes()[..], &88200u32.to_be_bytes(), &24u16.to_be_bytes(), &[0x40, 0x0e, 0xac, 0x44, 0, 0, 0, 0, 0, 0]].concat();
+        let bytes = riff(b"FORM", b"AIFF", &[chunk(b"COMM", &common, true), chunk(b"NAME", b"Drums", true), chunk(b"SSND", &[0; 9], true)]);
+        let file = TemporaryFile::new("read.aiff", &bytes);
+        let music_file = read(file.path(), true).unwrap();
+        assert_eq!((music_file.sample_rate, music_file.channels, music_file.bit_depth), (44100, 1, 24));
+        assert_eq!(music_file.duration, Duration::from_secs(2));
+        assert_eq!(music_file.title, "Drums");
+    }
+
+    #[test]
+    fn truncated_or_invalid_files_are_errors() {
+        let bytes = wav(&[]);
+        for length in [0, 8, 12, 20, 30] {
+            let file = TemporaryFile::new("truncated.wav", &bytes[..length]);
+            assert!(matches!(read(file.path(), false), Err(MedmanError::InvalidMedia(..))), "{}", length);
+        }
+        let file = TemporaryFile::new("avi.wav", &riff(b"RIFF", b"AVI ", &[format()]));
+        assert!(matches!(read(file.path(), false), Err(MedmanError::InvalidMedia(..))));
+        let file = TemporaryFile::new("truncated.aiff", &riff(b"FORM", b"AIFF", &[chunk(b"COMM", &[0; 10], true)]));
+        assert!(matches!(read(file.path(), true), Err(MedmanError::InvalidMedia(..))));
+        // Sous-chunk LIST/INFO dont la taille dépasse la fin du chunk
+        assert_eq!(sub_chunks(b"INAM\xff\x00\x00\x00abc"), [(&b"INAM"[..], &b"abc"[..])]);
+    }
+
+    #[test]
+    fn writes_id3_chunks_into_riff_files_only() {
+        let file = TemporaryFile::new("write.wav", &wav(&[(b"INAM", "Old title"), (b"IART", "Field Team")]));
+        let mut music_file = read(file.path(), false).unwrap();
+        music_file.title = "New title".to_string();
+        music_file.genre = "Ambient".to_string();
+        // Le média renvoyé est relu dans le fichier : taille et débit estimé après écriture
+        let written = write_tags(&music_file).unwrap();
+        assert_eq!(written.file_size, file.bytes().len() as u64);
+        assert_eq!(written.bitrate as u64, written.file_size * 8 / 2000);
+        assert_eq!((written.title.as_str(), written.genre.as_str()), ("New title", "Ambient"));
+        assert_eq!(written.duration, Duration::from_secs(2));
+
+        // Un champ du chunk LIST/INFO ne peut pas être effacé, contrairement à un champ du seul chunk ID3
+        music_file.author = String::new();
+        assert!(matches!(write_tags(&music_file), Err(MedmanError::TagWrite(..))));
+        let mut music_file = written;
+        music_file.genre = String::new();
+        write_tags(&music_file).unwrap();
+        assert_eq!(read(file.path(), false).unwrap().genre, "");
+
+        let mut bytes = wav(&[]);
+        bytes[..4].copy_from_slice(b"RF64");
+        let file = TemporaryFile::new("write_rf64.wav", &bytes);
+        let mut music_file = read(file.path(), false).unwrap();
+        music_file.title = "Title".to_string();
+        assert!(matches!(write_tags(&music_file), Err(MedmanError::TagWrite(..))));
+        assert_eq!(file.bytes(), bytes);
+    }
+}
@@ -1,70 +1,450 @@
-use std::path::Path;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::fs::metadata;
-use mp3_metadata::Genre;
+use std::panic;
+use std::sync::{mpsc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use id3::TagLike;
+use mp3_metadata::{ChannelType, Frame, Genre, Version};
 use walkdir::{DirEntry, WalkDir};
+use crate::error::MedmanError;
+use crate::flac::read_flac_metadata;
+use crate::matroska::read_matroska_metadata;
+use crate::media::{Media, MediaFile};
+use crate::mp4::{read_mp4_metadata, read_mp4_video_metadata};
 use crate::musicfile::MusicFile;
+use crate::ogg::read_ogg_metadata;
+use crate::photo::read_photo_metadata;
+use crate::photofile::PhotoFile;
+use crate::riff::{read_aiff_metadata, read_wav_metadata};
+use crate::stats::format_size;
+use crate::videofile::VideoFile;
 
 
-const SUPPORTED_EXTENSIONS: [&str; 1] = ["mp3"];
+const SUPPORTED_EXTENSIONS: [&str; 21] = ["mp3", "flac", "ogg", "oga", "opus", "m4a", "m4b", "wav", "aif", "aiff", "aifc",
+    "jpg", "jpeg", "tif", "tiff", "png", "mp4", "m4v", "mov", "mkv", "webm"];
 
+// Indique si l'entrée est un fichier dont l'extension (sans tenir compte de la casse) est supportée.
+// Les fichiers sans extension ou dont l'extension n'est pas de l'UTF-8 valide ne sont pas supportés
 fn is_supported(entry: &DirEntry) -> bool {
     entry.path().is_file() &&
-    SUPPORTED_EXTENSIONS.contains(&entry.path().extension().unwrap().to_str().unwrap())
-}
-
-pub fn scan(path: &Path) -> Vec<MusicFile> {
-    let mut music_files: Vec<MusicFile> = Vec::new();
-    let walker = WalkDir::new(path).into_iter();
-    for entry in walker {
-        match entry {
-            Ok(entry) => {
-                if is_supported(&entry) {
-                    // Initialisation de la structure pour chanque fichier de musique
-                    music_files.push(MusicFile::new(entry.path()));
+    extension(entry.path()).is_some_and(|extension| SUPPORTED_EXTENSIONS.contains(&extension.as_str()))
+}
+
+// Extension du fichier en minuscules, qui détermine le format du média
+pub(crate) fn extension(path: &Path) -> Option<String> {
+    path.extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_lowercase)
+}
+
+// Bilan d'une analyse de répertoire, avec la liste des fichiers ignorés et la raison de chaque erreur,
+// et le débit de lecture des fichiers (nombre et taille des fichiers lus, durée de l'analyse)
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    pub added: usize,
+    pub removed: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub skipped: Vec<MedmanError>,
+    pub read: usize,
+    pub bytes: u64,
+    pub elapsed: Duration,
+    pub workers: usize,
+}
+
+impl fmt::Display for ScanReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ajouté(s), {} supprimé(s), {} mis à jour, {} inchangé(s), {} ignoré(s)",
+            self.added, self.removed, self.updated, self.unchanged, self.skipped.len())?;
+        // Débit de lecture (les fichiers inchangés ne sont pas lus)
+        let seconds = self.elapsed.as_secs_f64();
+        write!(f, " ; {} fichier(s) lu(s) en {:.2} s avec {} thread(s)", self.read, seconds, self.workers)?;
+        if seconds > 0.0 && self.read > 0 {
+            write!(f, " ({:.0} fichiers/s, {}/s)", self.read as f64 / seconds, format_size((self.bytes as f64 / seconds) as u64))?;
+        }
+        Ok(())
+    }
+}
+
+
+// Nombre de threads de lecture par défaut : le nombre de cœurs disponibles
+pub fn default_workers() -> usize {
+    thread::available_parallelism().map(|workers| workers.get()).unwrap_or(1)
+}
+
+
+// Fichier supporté (ou erreur) rencontré lors du parcours d'un répertoire, dans l'ordre du parcours
+enum Scanned {
+    // Fichier repris tel quel d'un scan précédent
+    Unchanged(PathBuf),
+    // Fichier lu : son résultat est le suivant dans la liste des lectures
+    Read(PathBuf),
+    // Erreur de parcours d'un répertoire
+    Walk(walkdir::Error),
+}
+
+
+// Parcourt récursivement le répertoire 'path' et lit, avec 'workers' threads, les fichiers supportés
+// pour lesquels 'is_unchanged' renvoie false. Le parcours se poursuit pendant la lecture des premiers fichiers.
+// Renvoie les fichiers et erreurs rencontrés dans l'ordre du parcours, et les résultats des lectures
+// dans l'ordre des fichiers lus : le résultat est le même quel que soit le nombre de threads
+fn walk<F>(path: &Path, workers: usize, is_unchanged: F) -> (Vec<Scanned>, Vec<Result<MediaFile, MedmanError>>)
+    where F: Fn(&DirEntry) -> bool {
+    let mut scanned = Vec::new();
+    let to_read = WalkDir::new(path).into_iter().filter_map(|entry| match entry {
+        Ok(entry) if !is_supported(&entry) => None,
+        Ok(entry) if is_unchanged(&entry) => {
+            scanned.push(Scanned::Unchanged(entry.into_path()));
+            None
+        },
+        Ok(entry) => {
+            scanned.push(Scanned::Read(entry.path().to_path_buf()));
+            Some(entry.into_path())
+        },
+        Err(e) => {
+            scanned.push(Scanned::Walk(e));
+            None
+        },
+    });
+    // Une erreur inattendue (panique) lors de la lecture d'un fichier n'interrompt pas l'analyse :
+    // le fichier est ignoré comme un fichier illisible
+    let results = parallel_map(to_read, workers, |path| {
+        panic::catch_unwind(|| read_media(&path)).unwrap_or_else(|payload| {
+            let message = payload.downcast_ref::<&str>().map(|message| message.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_default();
+            Err(MedmanError::InvalidMedia(path, format!("erreur inattendue lors de la lecture : {}", message)))
+        })
+    });
+    (scanned, results)
+}
+
+
+// Applique 'f' aux éléments de 'items' avec 'workers' threads (au moins un). Les éléments sont distribués
+// aux threads au fur et à mesure de leur production par l'itérateur ; les résultats sont renvoyés
+// dans l'ordre des éléments
+fn parallel_map<T, R, I, F>(items: I, workers: usize, f: F) -> Vec<R>
+    where T: Send, R: Send, I: Iterator<Item = T>, F: Fn(T) -> R + Sync {
+    let (task_sender, task_receiver) = mpsc::channel::<(usize, T)>();
+    let (result_sender, result_receiver) = mpsc::channel::<(usize, R)>();
+    let task_receiver = Mutex::new(task_receiver);
+    let mut count = 0;
+    thread::scope(|scope| {
+        for _ in 0..workers.max(1) {
+            let (task_receiver, result_sender, f) = (&task_receiver, result_sender.clone(), &f);
+            scope.spawn(move || loop {
+                // Le verrou n'est conservé que le temps de recevoir un élément
+                let task = task_receiver.lock().unwrap().recv();
+                match task {
+                    Ok((position, item)) => if result_sender.send((position, f(item))).is_err() {
+                        break;
+                    },
+                    Err(_) => break,
                 }
+            });
+        }
+        drop(result_sender);
+        for (position, item) in items.enumerate() {
+            task_sender.send((position, item)).unwrap();
+            count += 1;
+        }
+        drop(task_sender);
+    });
+    let mut results: Vec<Option<R>> = (0..count).map(|_| None).collect();
+    for (position, result) in result_receiver {
+        results[position] = Some(result);
+    }
+    results.into_iter().map(|result| result.unwrap()).collect()
+}
+
+
+// Réanalyse le répertoire 'path' à partir des fichiers d'un scan précédent, avec 'workers' threads de lecture :
+// seuls les fichiers nouveaux ou dont la taille ou la date de modification a changé sont relus,
+// les autres sont repris tels quels. Les fichiers qui n'existent plus sont retirés.
+// Renvoie la nouvelle liste des médias ainsi que le bilan de l'analyse ; les fichiers illisibles
+// sont ignorés et listés dans le bilan
+pub fn rescan(path: &Path, previous: &[MediaFile], workers: usize) -> (Vec<MediaFile>, ScanReport) {
+    let start = Instant::now();
+    let mut report = ScanReport { workers: workers.max(1), ..ScanReport::default() };
+    // Index des fichiers précédents par chemin
+    let known: HashMap<&Path, &MediaFile> = previous.iter()
+        .map(|media| (media.path(), media))
+        .collect();
+    // Lecture des métadonnées des seuls fichiers nouveaux ou modifiés
+    let (scanned, results) = walk(path, workers, |entry| {
+        known.get(entry.path()).is_some_and(|old| is_unchanged(entry, *old))
+    });
+    let mut results = results.into_iter();
+    // Nombre de fichiers précédents toujours présents
+    let mut found = 0;
+    let mut medias: Vec<MediaFile> = Vec::new();
+    for scanned in scanned {
+        match scanned {
+            Scanned::Walk(e) => report.skipped.push(MedmanError::Walk(e)),
+            Scanned::Unchanged(file_path) => {
+                found += 1;
+                report.unchanged += 1;
+                medias.push(known[file_path.as_path()].clone());
+            },
+            Scanned::Read(file_path) => {
+                let old = known.get(file_path.as_path());
+                if old.is_some() {
+                    found += 1;
+                }
+                report.read += 1;
+                match results.next().unwrap() {
+                    Ok(mut media) => {
+                        report.bytes += media.size();
+                        // Les mots-clés conservés uniquement dans le catalogue sont repris,
+                        // sauf si le fichier contient ses propres mots-clés
+                        let old_audio = old.and_then(|old| old.as_audio());
+                        if let (Some(music_file), Some(old)) = (media.as_audio_mut(), old_audio) {
+                            if music_file.keywords.is_empty() {
+                                music_file.keywords = old.keywords.clone();
+                            }
+                        }
+                        if old.is_some() { report.updated += 1 } else { report.added += 1 }
+                        medias.push(media);
+                    },
+                    Err(e) => report.skipped.push(e),
+                }
+            },
+        }
+    }
+    report.removed = previous.len().saturating_sub(found);
+    report.elapsed = start.elapsed();
+    (medias, report)
+}
+
+
+// Lit le média correspondant au fichier 'path'
+pub(crate) fn read_media(path: &Path) -> Result<MediaFile, MedmanError> {
+    if path.to_str().is_none() {
+        return Err(MedmanError::InvalidPath(path.to_path_buf()));
+    }
+    match extension(path).as_deref() {
+        Some("jpg") | Some("jpeg") | Some("tif") | Some("tiff") | Some("png") => {
+            let mut photo_file = PhotoFile::new(path);
+            read_photo_metadata(&mut photo_file)?;
+            Ok(MediaFile::Photo(photo_file))
+        },
+        Some("mp4") | Some("m4v") | Some("mov") | Some("mkv") | Some("webm") =>
+            read_video_metadata(VideoFile::new(path)).map(MediaFile::Video),
+        _ => read_metadata(MusicFile::new(path)).map(MediaFile::Audio),
+    }
+}
+
+
+// Renvoie la vidéo passée en paramètre complétée par les métadonnées lues dans le fichier,
+// ou l'erreur rencontrée lors de la lecture
+fn read_video_metadata(mut video_file: VideoFile) -> Result<VideoFile, MedmanError> {
+    match metadata(&video_file.path) {
+        Ok(meta) => {
+            video_file.file_size = meta.len();
+            if let Ok(modified) = meta.modified() {
+                video_file.modified = modified;
             }
-            // Gestion d'erreur
-            Err(e) => panic!("Error while scaning music file : {:?}\n{}", e.path(), e),
-        };
+        },
+        Err(e) => return Err(MedmanError::Io(video_file.path, e)),
+    }
+
+    match extension(&video_file.path).as_deref() {
+        Some("mkv") | Some("webm") => read_matroska_metadata(&mut video_file)?,
+        _ => read_mp4_video_metadata(&mut video_file)?,
+    }
+    Ok(video_file)
+}
+
+
+// Indique si le fichier 'entry' a la même taille et la même date de modification que
+// le média 'media' issu d'un scan précédent
+fn is_unchanged(entry: &DirEntry, media: &dyn Media) -> bool {
+    match entry.metadata() {
+        Ok(meta) => meta.len() == media.size()
+            && meta.modified().ok() == Some(media.modified()),
+        Err(_) => false,
     }
-    // Remplissage des métadonnées
-    music_files = fill_files_metadata(music_files);
-    music_files 
 }
 
 
-// Clone la liste des médias en  les rajoutant les Tags nécessaires pour les fichiers mp3
-pub fn fill_files_metadata(music_files: Vec<MusicFile>) -> Vec<MusicFile> {
-    let mut result: Vec<MusicFile> = Vec::new(); // Nouveau vecteur résultat
-    music_files.into_iter().for_each(|mut music_file| { // Parcourt de l'ancien vecteur
-        // Assignation de la taille du fichier
-        match metadata(music_file.get_file_path()) {
-            Ok(meta) => music_file.file_size = meta.len(),
-            Err(e) => panic!("Error when collecting music files metadata : {}", e)
+// Renvoie le média passé en paramètre complété par les métadonnées lues dans le fichier,
+// ou l'erreur rencontrée lors de la lecture
+fn read_metadata(mut music_file: MusicFile) -> Result<MusicFile, MedmanError> {
+    // Assignation de la taille et de la date de modification du fichier
+    match metadata(music_file.get_file_path()) {
+        Ok(meta) => {
+            music_file.file_size = meta.len();
+            if let Ok(modified) = meta.modified() {
+                music_file.modified = modified;
+            }
+        },
+        Err(e) => return Err(MedmanError::Io(music_file.path, e)),
+    }
+
+    match extension(&music_file.path).as_deref() {
+        Some("flac") => read_flac_metadata(&mut music_file)?,
+        Some("ogg") | Some("oga") | Some("opus") => read_ogg_metadata(&mut music_file)?,
+        Some("m4a") | Some("m4b") => read_mp4_metadata(&mut music_file)?,
+        Some("wav") => read_wav_metadata(&mut music_file)?,
+        Some("aif") | Some("aiff") | Some("aifc") => read_aiff_metadata(&mut music_file)?,
+        _ => read_mp3_metadata(&mut music_file)?,
+    }
+
+    // Débit moyen des autres formats, estimé à partir de la taille du fichier (métadonnées comprises) :
+    // en bits par milliseconde, soit en kbit/s
+    if music_file.bitrate == 0 {
+        if let Some(bitrate) = (music_file.file_size * 8).checked_div(music_file.duration.as_millis() as u64) {
+            music_file.bitrate = bitrate as u32;
         }
+    }
+    Ok(music_file)
+}
 
-        // Récupération puis assignation des matadonnées mp3
-        match mp3_metadata::read_from_file(music_file.get_file_path()) {
 
-            Ok(mp3_metadata) => {
+// Complète le média avec la durée et les tags ID3v1 et ID3v2 d'un fichier mp3
+fn read_mp3_metadata(music_file: &mut MusicFile) -> Result<(), MedmanError> {
+    // Récupération puis assignation des matadonnées mp3
+    match mp3_metadata::read_from_file(music_file.get_file_path()) {
 
-                music_file.duration = mp3_metadata.duration;
-                
-                if let Some(audio_tag) = mp3_metadata.tag {	
-                    music_file.author = audio_tag.artist.replace(" ", "_").trim_matches(char::from(0)).to_string();
-                    music_file.title = audio_tag.title.replace(" ", "_").trim_matches(char::from(0)).to_string();
-                    music_file.album = audio_tag.album.replace(" ", "_").trim_matches(char::from(0)).to_string();
-                    music_file.year = audio_tag.year;
-                    music_file.genre = get_media_genre(audio_tag.genre);
-                }
-            },
+        Ok(mp3_metadata) => {
 
-            Err(e) => panic!("Error when collecting music files metadata : {}", e)
+            music_file.duration = mp3_metadata.duration;
+            fill_mp3_stream(music_file, &mp3_metadata.frames);
+
+            // Les champs texte du tag ID3v1 sont lus ci-dessous avec le crate id3
+            if let Some(audio_tag) = mp3_metadata.tag {
+                music_file.year = audio_tag.year;
+                music_file.genre = get_media_genre(audio_tag.genre);
+            }
+        },
+
+        Err(e) => return Err(MedmanError::InvalidMedia(music_file.path.clone(), e.to_string())),
+    }
+
+    // Champs texte du tag ID3v1 : mp3_metadata ne décode pas le Latin-1 (un champ accentué est lu vide),
+    // ils sont donc lus avec le crate id3, ainsi que le commentaire et le numéro de piste (ID3v1.1)
+    if let Ok(tag) = id3::v1::Tag::read_from_path(&music_file.path) {
+        music_file.title = clean_tag(&tag.title);
+        music_file.author = clean_tag(&tag.artist);
+        music_file.album = clean_tag(&tag.album);
+        music_file.comment = clean_tag(&tag.comment);
+        music_file.track = tag.track.unwrap_or(0) as u32;
+    }
+
+    // Les tags ID3v2, plus complets, sont prioritaires sur les tags ID3v1.
+    // Un fichier sans tag ID3v2 (ou dont le tag est illisible) conserve ses tags ID3v1
+    if let Ok(tag) = id3::Tag::read_from_path(&music_file.path) {
+        fill_id3v2_tags(music_file, &tag);
+    }
+    Ok(())
+}
+
+
+// Complète le média avec les propriétés du flux mp3 lues dans les en-têtes de ses trames : débit moyen,
+// débit variable (trames de débits différents), et fréquence d'échantillonnage, nombre de canaux
+// et version MPEG de la première trame
+fn fill_mp3_stream(music_file: &mut MusicFile, frames: &[Frame]) {
+    let first = match frames.first() {
+        Some(first) => first,
+        None => return,
+    };
+    music_file.sample_rate = first.sampling_freq as u32;
+    music_file.channels = if first.chan_type == ChannelType::SingleChannel { 1 } else { 2 };
+    music_file.mpeg_version = match first.version {
+        Version::MPEG1 => "1",
+        Version::MPEG2 => "2",
+        Version::MPEG2_5 => "2.5",
+        _ => "",
+    }.to_string();
+    let total: u64 = frames.iter().map(|frame| frame.bitrate as u64).sum();
+    music_file.bitrate = (total / frames.len() as u64) as u32;
+    music_file.vbr = frames.iter().any(|frame| frame.bitrate != first.bitrate);
+}
+
+
+// Complète le média avec les frames d'un tag ID3v2 (versions 2.2, 2.3 et 2.4).
+// Seules les frames présentes et non vides remplacent les valeurs déjà lues
+pub(crate) fn fill_id3v2_tags(music_file: &mut MusicFile, tag: &id3::Tag) {
+    let texts = [
+        (&mut music_file.title, tag.title()),
+        (&mut music_file.author, tag.artist()),
+        (&mut music_file.album_artist, tag.album_artist()),
+        (&mut music_file.album, tag.album()),
+        (&mut music_file.composer, tag.text_for_frame_id("TCOM")),
+    ];
+    for (field, value) in texts {
+        if let Some(value) = value.map(clean_frame_text).filter(|value| !value.is_empty()) {
+            *field = value;
         }
+    }
+    // Genre : les références aux genres ID3v1 ("(17)", "17") sont converties en nom, le texte libre est conservé
+    if let Some(genre) = tag.genre_parsed().map(|genre| clean_frame_text(&genre)).filter(|genre| !genre.is_empty()) {
+        music_file.genre = genre;
+    }
+    if let Some(track) = tag.track() {
+        music_file.track = track;
+    }
+    if let Some(disc) = tag.disc() {
+        music_file.disc = disc;
+    }
+    if let Some(year) = id3v2_year(tag).and_then(|year| u16::try_from(year).ok()) {
+        music_file.year = year;
+    }
+    // Commentaire : celui sans description de préférence (les autres sont souvent des données techniques)
+    let comment = tag.comments().find(|comment| comment.description.is_empty())
+        .or_else(|| tag.comments().next());
+    if let Some(comment) = comment.map(|comment| clean_frame_text(&comment.text)).filter(|comment| !comment.is_empty()) {
+        music_file.comment = comment;
+    }
+    if let Some(keywords) = id3v2_keywords(tag) {
+        music_file.keywords = keywords;
+    }
+}
 
-        result.push(music_file)
-    });
-    result
+
+// Description de la frame TXXX contenant les mots-clés d'un média
+pub(crate) const KEYWORDS_FRAME: &str = "KEYWORDS";
+
+
+// Mots-clés d'un tag ID3v2, lus dans la frame TXXX:KEYWORDS (séparés par des ";" ou des caractères nuls)
+pub(crate) fn id3v2_keywords(tag: &id3::Tag) -> Option<Vec<String>> {
+    tag.extended_texts()
+        .find(|text| text.description.eq_ignore_ascii_case(KEYWORDS_FRAME))
+        .map(|text| text.value.split([';', '\0'])
+            .map(str::trim)
+            .filter(|keyword| !keyword.is_empty())
+            .map(str::to_string)
+            .collect())
+}
+
+
+// Année d'un tag ID3v2 : TYER (ID3v2.3) ou date d'enregistrement TDRC (ID3v2.4), à défaut date de sortie
+pub(crate) fn id3v2_year(tag: &id3::Tag) -> Option<i32> {
+    tag.year()
+        .or_else(|| tag.date_recorded().map(|date| date.year))
+        .or_else(|| tag.date_released().map(|date| date.year))
+}
+
+
+// Nettoie le texte d'une frame ID3v2 : les valeurs multiples (séparées par des caractères nuls
+// en ID3v2.4) sont séparées par "; "
+pub(crate) fn clean_frame_text(value: &str) -> String {
+    value.split('\0')
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<&str>>()
+        .join("; ")
+}
+
+
+// Nettoie un champ texte d'un tag en conservant le texte d'origine :
+// seuls les caractères de remplissage (nuls et espaces en fin de champ) sont retirés
+fn clean_tag(value: &str) -> String {
+    value.trim_matches(char::from(0)).trim_end().to_string()
 }
 
 
@@ -1,156 +1,453 @@
-use crate::musicfile::MusicFile;
-extern crate parse_duration;
-use parse_duration::parse;
+use std::cmp::Ordering;
+use std::path::Path;
+use glob::MatchOptions;
+use serde::Serialize;
+use strsim::damerau_levenshtein;
+use unicode_normalization::UnicodeNormalization;
+use unicode_normalization::char::is_combining_mark;
+use crate::catalog::Catalog;
+use crate::index::tokenize;
+use crate::media::{search_fields, FieldValue, Media, MediaFile};
+use crate::query::{self, Matcher, Query, Request, SortField, SortKey, WildcardToken};
 
-// Représente les champs possibles dans une requête de recherche sur les données gérées
-#[derive(Debug, Clone)]
-pub enum SearchField {
+// Type de la valeur d'un champ de recherche, qui détermine la syntaxe acceptée dans les requêtes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldType {
+    // Chemin du fichier : motifs glob, complet ou relatif au répertoire scanné
     Path,
+    // Texte, éventuellement à plusieurs valeurs (mots-clés)
+    Text,
+    // Entier positif
+    Number,
+    // Taille en octets (les requêtes acceptent les unités KB, MB...)
     Size,
-    Title,
-    Author,
-    Duration, 
-    Album,
-    Year,
-    Genre,
-    Undefined
+    // Durée en millisecondes (les requêtes acceptent les durées lisibles : 2min45s)
+    Duration,
+    // Date et heure (voir media::date_value ; les requêtes acceptent les dates partielles : 2019, 2019-05)
+    Date,
+    // Coordonnée GPS (voir media::coordinate_value ; les requêtes acceptent les degrés décimaux : -2.29)
+    Coordinate,
 }
 
-// Prend en entrée une chaîne de caractère qui correpond au champ de la recherche et renvoie
-// l'objet SearchType correspondant
-pub fn str_to_search_field(str_field: &str) -> SearchField {
-    match str_field {
-        "path" => SearchField::Path,
-        "size" => SearchField::Size,
-        "title" => SearchField::Title,
-        "author" => SearchField::Author,
-        "duration" => SearchField::Duration,
-        "album" => SearchField::Album,
-        "year" => SearchField::Year,
-        "genre" => SearchField::Genre,
-        _ => SearchField::Undefined
-    }
+// Représente un champ possible dans une requête de recherche sur les données gérées.
+// Les champs sont déclarés par les types de médias (voir media::search_fields)
+// et leurs valeurs lues par leur nom (voir Media::field)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SearchField {
+    name: &'static str,
+    aliases: &'static [&'static str],
+    field_type: FieldType,
+    // Poids utilisé pour classer les résultats d'un terme libre selon le champ où il a été trouvé
+    // (0 pour un champ dans lequel les termes libres ne sont pas recherchés)
+    weight: u32,
 }
 
+impl SearchField {
+    pub const fn new(name: &'static str, field_type: FieldType) -> SearchField {
+        SearchField { name, aliases: &[], field_type, weight: 0 }
+    }
+
+    // Champ dans lequel sont recherchés les termes libres, avec le poids 'weight'
+    pub const fn weighted(self, weight: u32) -> SearchField {
+        SearchField { weight, ..self }
+    }
 
-// Prend en entrée une chaine de caractères qui correspond à la requête de la recherche 
-// et renvoie une liste de paire (clé de la requête, valeur), correspondant aux sous requêtes.
-// Le format d'une recherche doit être le suivant : champ1:valeur1 champ2:valeur2 ...
-// La valeur à rechercher doit être séparer du champ par ":" sans espace. Chaque sous requête doit
-// cependant être séparé du suivant par un espace.
-// Exemple : title:Titre1 author:Auteur1 duration:20 genre:champ avec espaces
-pub fn parse_request(request: &str) -> Vec<(SearchField, String)> {
-    let mut result: Vec<(SearchField, String)> = Vec::new();
-    // On split la chaine de caractère avec des espaces pour récupérer les sous requêtes dans un tableau
-    let subrequests: Vec<&str> = request.split_whitespace().collect(); 
-    // Pour chaque sous requête
-    for subrequest in subrequests {
-      // On récupère le champ et la valeur (split de la chaine avec ":"")
-        let fields: Vec<&str> = subrequest.split(":").collect();
-        // Si la taille du vecteur récupéré est < 2 i.e le champ ou la valeur de la requête est manquante
-        // Alors il y a erreur, on ignore simplement cette sous requête et on passe à la suivante 
-        // (en affichant un message d'erreur)
-        if fields.len() < 2 {
-          // Affichage du message d'erreur sur la sortie standard d'erreur
-          eprintln!("Err : Incorrect format of request : Subrequest is : {}, The full request is : {}", subrequest, request)
-        } else {
-          // Sinon, on converti le champ en SearchField et on ajoute la paire à la liste résultat
-            result.push((str_to_search_field(fields[0]), fields[1].to_string()))
-        }
+    // Champ pouvant aussi être désigné par les noms 'aliases' dans les requêtes
+    pub const fn aliased(self, aliases: &'static [&'static str]) -> SearchField {
+        SearchField { aliases, ..self }
     }
-    result
-}
-
-// Prend en entrée la liste des médias et le vecteur contenant les champs de la recherche
-// et les valeurs associées, et renvoie les médias correspondant à la recherche
-pub fn search(music_files: Vec<MusicFile>, vec_req: Vec<(SearchField, String)>) -> Vec<MusicFile> {
-  // Liste des médias résultat
-  let mut result_files: Vec<MusicFile> = Vec::new();
-  // Pour chaque média,
-  music_files.into_iter().for_each(|music_file| { 
-
-    // Parcoure les sous requêtes de la recherche
-    vec_req.clone().into_iter().for_each(|subrequest| { 
-      // Vérifie le champ de la recherche
-      match subrequest.0 {
-        // Si le champ est le chemin
-        SearchField::Path => 
-          // Conversion du Path en String puis comparaison à la valeur
-          // (on peut utiliser unwrap ici car into_string empaque <String, OsString>, unwrap renvoie la partie String)
-          if music_file.get_file_path().into_os_string().into_string().unwrap() == subrequest.1 {
-            // Si la valeur correspond au chemin du média
-            // Alors on ajoute le média à la liste résultat
-            result_files.push(music_file.clone());
-          },
-
-        // Si le champ est la taille
-        SearchField::Size =>
-          // On converti la taille spécifée en entier
-          match subrequest.1.parse::<u64>() {
-            // Si la conversion réussi et que la valeur correspond à la taille du média,
-            // alors on ajoute le média à la liste résultat
-            Ok (val) => if music_file.file_size == val { result_files.push(music_file.clone()) },
-            // Sinon, on affiche un message d'erreur et on ignore la sous requête
-            Err(_) => eprintln!("Mauvais format de la sous requête : {:?} : {} n'est pas un entier positif", subrequest, subrequest.1),
-          },
-
-        // Si le champ est le titre et que la valeur correspond au titre du média
-        SearchField::Title =>
-          if music_file.title == subrequest.1 {
-            // Alors on ajoute le média à la liste résultat
-            result_files.push(music_file.clone());
-          },        
-
-        // Si le champ est l'auteur et que la valeur correspond à l'auteur du média
-        SearchField::Author =>
-          if music_file.author == subrequest.1 {
-            // Alors on ajoute le média à la liste résultat
-            result_files.push(music_file.clone());
-          },
-
-        // Si le champ est la durée
-        SearchField::Duration =>
-          // On converti la durée spécifée en Duration
-          match parse(subrequest.1.clone().as_mut_str()) {
-            // Si la conversion réussi et que la valeur correspond à la durée du média,
-            // alors on ajoute le média à la liste résultat
-            Ok (d) => if music_file.duration == d { result_files.push(music_file.clone()) },
-            // Sinon, on affiche un message d'erreur et on ignore la sous requête
-            Err(_) => eprintln!("Mauvais format de la sous requête : {:?}, {:?} durée incorrecte", subrequest, subrequest.1),
-          },
-     
-        // Si le champ est l'album et que la valeur correspond à l'album du média
-        SearchField::Album =>
-          if music_file.album == subrequest.1 {
-            // Alors on ajoute le média à la liste résultat
-            result_files.push(music_file.clone());
-          },
-
-        // Si le champ est l'année
-        SearchField::Year =>
-          // On converti l'année spécifée en entier
-          match subrequest.1.parse::<u16>() {
-            // Si la conversion réussi et que la valeur correspond à l'année du média,
-            // alors on ajoute le média à la liste résultat
-            Ok (val) => if music_file.year == val { result_files.push(music_file.clone()) },
-            // Sinon, on affiche un message d'erreur et on ignore la sous requête
-            Err(_) => eprintln!("Mauvais format de la sous requête : {:?}, {} n'est pas un entier", subrequest, subrequest.1),
-          },
-
-        // Si le champ est le genre et que la valeur correspond au genre  du média
-        SearchField::Genre =>
-        if music_file.genre == subrequest.1 {
-          // Alors on ajoute le média à la liste résultat
-          result_files.push(music_file.clone());
-        },
+
+    // Nom du champ dans les requêtes (inverse de str_to_search_field)
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    // Type de la valeur du champ
+    pub fn field_type(&self) -> FieldType {
+        self.field_type
+    }
+
+    // Poids du champ pour les termes libres
+    pub fn weight(&self) -> u32 {
+        self.weight
+    }
+
+    // Indique si le champ est numérique (il accepte alors les intervalles et les comparaisons)
+    pub fn is_numeric(&self) -> bool {
+        !matches!(self.field_type, FieldType::Path | FieldType::Text)
+    }
+}
+
+// Prend en entrée une chaîne de caractère qui correpond au champ de la recherche et renvoie
+// le champ correspondant, ou None si aucun type de média ne déclare ce champ
+pub fn str_to_search_field(str_field: &str) -> Option<SearchField> {
+    search_fields().iter()
+        .find(|field| field.name == str_field || field.aliases.contains(&str_field))
+        .copied()
+}
+
+
+// Champs dans lesquels sont recherchés les termes libres
+fn free_text_fields() -> impl Iterator<Item = &'static SearchField> {
+    search_fields().iter().filter(|field| field.weight > 0)
+}
+
+
+// Prend en entrée une chaine de caractères qui correspond à la requête de la recherche
+// et renvoie l'arbre syntaxique de la requête (voir le module query pour la syntaxe).
+// Un terme sans champ est recherché dans tous les champs texte.
+// Le format d'une sous requête est champ:valeur (suivi éventuellement d'une pondération ^B), la valeur étant séparée du champ par ":" sans espace.
+// Les sous requêtes sont séparées par des espaces et combinées par un ET implicite,
+// ou explicitement par les opérateurs AND, OR, NOT, +, - et des parenthèses.
+// Les options sort:, limit: et offset: précisent l'ordre et la pagination des résultats.
+// Exemple : title:Titre1 (author:Auteur1 OR author:Auteur2) -genre:Rock sort:year desc limit:50
+pub fn parse_request(request: &str) -> Result<Request, String> {
+    query::parse(request)
+}
+
+
+// Paramètres du modèle de pertinence BM25
+const BM25_K1: f32 = 1.2;
+const BM25_B: f32 = 0.75;
+
+
+// Résultat d'une recherche : un média et son score de pertinence
+#[derive(Debug, Clone, Serialize)]
+pub struct Hit {
+  pub score: f32,
+  #[serde(flatten)]
+  pub media: MediaFile,
+}
+
+
+// Prend en entrée le catalogue des médias et la requête, et renvoie les médias correspondant
+// à la recherche. La requête est évaluée à l'aide de l'index du catalogue ; chaque média
+// apparaît au plus une fois. Les résultats sont classés par score de pertinence décroissant
+// (voir score), puis dans l'ordre du catalogue, sauf si la requête précise des critères de tri.
+// Les options offset: et limit: sélectionnent ensuite la page de résultats demandée
+pub fn search(catalog: &Catalog, request: &Request) -> Vec<Hit> {
+  let query = &request.query;
+  let mut hits: Vec<Hit> = catalog.index().evaluate(query, catalog.files(), catalog.root())
+    .into_iter()
+    .map(|doc| {
+      let media = &catalog.files()[doc as usize];
+      Hit { score: score(query, media, catalog), media: media.clone() }
+    })
+    .collect();
+  // Tri stable : à score égal, l'ordre du catalogue est conservé
+  hits.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(Ordering::Equal));
+  let options = &request.options;
+  if !options.sort.is_empty() {
+    hits = sort_hits(hits, &options.sort, catalog.root());
+  }
+  hits.into_iter()
+    .skip(options.offset)
+    .take(options.limit.unwrap_or(usize::MAX))
+    .collect()
+}
+
+
+// Valeur d'un résultat pour un critère de tri
+#[derive(Debug, Clone, PartialEq, PartialOrd)]
+enum SortValue {
+  Score(f32),
+  Number(Option<u64>),
+  Text(Option<String>),
+}
 
 
-        // Si le champ est indéfini, alors la sous requête n'est pas valide. On affiche un message d'erreur 
-        // et on ignore la sous requête
-        SearchField::Undefined => eprintln!("Mauvais format de la sous requête : {:?}", subrequest),
-        }
-      });
+impl SortValue {
+  // Indique si le média n'a pas de valeur pour le critère (champ absent, nul ou vide)
+  fn is_missing(&self) -> bool {
+    matches!(self, SortValue::Number(None) | SortValue::Text(None))
+  }
+}
+
+
+// Trie les résultats selon les critères 'keys', appliqués dans l'ordre. Les champs texte sont
+// comparés sans tenir compte de la casse ni des accents (le chemin relativement au répertoire scanné).
+// Les médias sans valeur pour le champ (nombre inconnu, texte vide) sont placés après les autres, quel que soit
+// le sens du tri.
+// Le tri est stable : à valeurs égales, l'ordre de pertinence est conservé
+fn sort_hits(hits: Vec<Hit>, keys: &[SortKey], root: &Path) -> Vec<Hit> {
+  // Les valeurs de tri sont calculées une seule fois par résultat
+  let mut decorated: Vec<(Vec<SortValue>, Hit)> = hits.into_iter()
+    .map(|hit| (keys.iter().map(|key| sort_value(&key.field, &hit, root)).collect(), hit))
+    .collect();
+  decorated.sort_by(|(a, _), (b, _)| {
+    keys.iter().zip(a.iter().zip(b.iter()))
+      .map(|(key, (a, b))| match (a.is_missing(), b.is_missing()) {
+        (true, false) => Ordering::Greater,
+        (false, true) => Ordering::Less,
+        _ => {
+          let ordering = a.partial_cmp(b).unwrap_or(Ordering::Equal);
+          if key.descending { ordering.reverse() } else { ordering }
+        },
+      })
+      .find(|ordering| *ordering != Ordering::Equal)
+      .unwrap_or(Ordering::Equal)
   });
+  decorated.into_iter().map(|(_, hit)| hit).collect()
+}
+
+
+// Renvoie la valeur de tri d'un résultat pour le champ 'field'
+fn sort_value(field: &SortField, hit: &Hit, root: &Path) -> SortValue {
+  match field {
+    SortField::Score => SortValue::Score(hit.score),
+    SortField::Field(field) if field.is_numeric() => SortValue::Number(numeric_value(field, &hit.media)),
+    SortField::Field(field) => {
+      let text = normalize(&free_text_value(field, &hit.media, root));
+      SortValue::Text(Some(text).filter(|text| !text.is_empty()))
+    },
+  }
+}
+
+
+// Indique si le média satisfait la requête. 'root' est le répertoire scanné,
+// par rapport auquel sont évalués les motifs portant sur le chemin
+pub fn matches(query: &Query, media: &dyn Media, root: &Path) -> bool {
+  match query {
+    Query::Term(field, value) => term_matches(field, value, media, root),
+    Query::FreeText(matcher) => free_text_fields().any(|field| free_text_matches(field, matcher, media, root)),
+    Query::And(operands) => operands.iter().all(|operand| matches(operand, media, root)),
+    Query::Or(operands) => operands.iter().any(|operand| matches(operand, media, root)),
+    Query::Not(operand) => !matches(operand, media, root),
+    Query::Boost(operand, _) => matches(operand, media, root),
+  }
+}
 
-  result_files
-}
\ No newline at end of file
+
+// Score de pertinence d'un média satisfaisant la requête :
+// - un terme libre est évalué avec le modèle BM25 dans les champs texte ayant un poids (title, author,
+//   album, genre et keyword pour les fichiers audio), pondéré par ce poids (voir SearchField::weight) ;
+// - un terme portant sur un champ texte vaut l'IDF de la valeur du média (une valeur rare compte plus) ;
+// - un terme portant sur un champ numérique ou un motif de chemin vaut 1 ;
+// - les scores des sous requêtes satisfaites s'additionnent, une négation vaut 0
+//   et une pondération (^B) multiplie le score de sa sous requête
+pub fn score(query: &Query, media: &dyn Media, catalog: &Catalog) -> f32 {
+  let index = catalog.index();
+  match query {
+    Query::FreeText(matcher) => free_text_fields()
+      .filter(|field| field.field_type() != FieldType::Path)
+      .map(|field| field.weight() as f32 * bm25(field, matcher, media, catalog))
+      .sum(),
+    Query::Term(field, _) if field.field_type() == FieldType::Path || field.is_numeric() => 1.0,
+    // Pour un champ à plusieurs valeurs (mots-clés), la plus rare des valeurs satisfaisant la condition compte
+    Query::Term(field, matcher) => text_values(field, media, catalog.root()).iter()
+      .filter(|text| text_matches(matcher, text))
+      .map(|text| idf(index.value_frequency(field, &normalize(text)), index.doc_count()))
+      .fold(0.0, f32::max),
+    // La somme part de 0 (et non de -0) pour qu'une requête sans condition ait un score nul
+    Query::And(operands) => operands.iter()
+      .fold(0.0, |total, operand| total + score(operand, media, catalog)),
+    Query::Or(operands) => operands.iter()
+      .filter(|operand| matches(operand, media, catalog.root()))
+      .fold(0.0, |total, operand| total + score(operand, media, catalog)),
+    Query::Not(_) => 0.0,
+    Query::Boost(operand, boost) => boost * score(operand, media, catalog),
+  }
+}
+
+
+// Score BM25 d'un terme libre dans le champ texte 'field' du média. Pour une phrase, les scores
+// de ses mots s'additionnent ; pour un motif, ceux des mots du champ qui lui correspondent
+fn bm25(field: &SearchField, matcher: &Matcher, media: &dyn Media, catalog: &Catalog) -> f32 {
+  if !free_text_matches(field, matcher, media, catalog.root()) {
+    return 0.0;
+  }
+  let index = catalog.index();
+  let words = tokenize(&normalize(&free_text_value(field, media, catalog.root())));
+  let mut terms: Vec<String> = match matcher {
+    Matcher::Text(phrase) => tokenize(phrase),
+    _ => words.iter().filter(|word| text_matches(matcher, word)).cloned().collect(),
+  };
+  terms.sort_unstable();
+  terms.dedup();
+  let length_ratio = match index.average_length(field) {
+    average if average > 0.0 => words.len() as f32 / average,
+    _ => 1.0,
+  };
+  terms.iter()
+    .map(|term| {
+      let frequency = words.iter().filter(|word| *word == term).count() as f32;
+      let idf = idf(index.document_frequency(field, term), index.doc_count());
+      idf * frequency * (BM25_K1 + 1.0) / (frequency + BM25_K1 * (1.0 - BM25_B + BM25_B * length_ratio))
+    })
+    .sum()
+}
+
+
+// Fréquence documentaire inverse (variante BM25, toujours positive) d'un mot ou d'une valeur
+// présent dans 'frequency' documents parmi 'doc_count'
+fn idf(frequency: usize, doc_count: u32) -> f32 {
+  let (frequency, doc_count) = (frequency as f32, doc_count as f32);
+  (1.0 + (doc_count - frequency + 0.5) / (frequency + 0.5)).ln()
+}
+
+
+// Indique si un terme libre apparaît dans les mots du champ 'field' du média.
+// Une valeur de plusieurs mots est une phrase dont les mots doivent se suivre
+pub(crate) fn free_text_matches(field: &SearchField, matcher: &Matcher, media: &dyn Media, root: &Path) -> bool {
+  let words = tokenize(&normalize(&free_text_value(field, media, root)));
+  match matcher {
+    Matcher::Text(phrase) => {
+      let phrase = tokenize(phrase);
+      !phrase.is_empty() && words.windows(phrase.len()).any(|window| window == phrase.as_slice())
+    },
+    _ => words.iter().any(|word| text_matches(matcher, word)),
+  }
+}
+
+
+// Valeur d'un champ texte dans laquelle sont recherchés les termes libres.
+// Seule la partie du chemin relative au répertoire scanné est prise en compte
+pub(crate) fn free_text_value(field: &SearchField, media: &dyn Media, root: &Path) -> String {
+  match field.field_type() {
+    FieldType::Path => relative_path(media, root),
+    _ => text_values(field, media, root).join(" "),
+  }
+}
+
+
+// Indique si la valeur du champ 'field' du média satisfait la condition 'matcher'
+pub(crate) fn term_matches(field: &SearchField, matcher: &Matcher, media: &dyn Media, root: &Path) -> bool {
+  match matcher {
+    Matcher::Glob(pattern, exact) => {
+      let options = MatchOptions { case_sensitive: true, require_literal_separator: true, require_literal_leading_dot: false };
+      // Un motif absolu porte sur le chemin complet, sinon sur le chemin relatif au répertoire scanné
+      let path = if pattern.as_str().starts_with('/') {
+        media.path().to_string_lossy().into_owned()
+      } else {
+        relative_path(media, root)
+      };
+      if *exact { pattern.matches_with(&path, options) } else { pattern.matches_with(&normalize(&path), options) }
+    },
+    Matcher::Range(min, max) => numeric_value(field, media)
+      .is_some_and(|val| val >= *min && max.is_none_or(|max| val < max)),
+    _ => text_values(field, media, root).iter().any(|text| text_matches(matcher, text)),
+  }
+}
+
+
+// Indique si une valeur texte satisfait la condition 'matcher'. Hors mode exact,
+// la valeur est normalisée avant comparaison (le résultat est donc le même pour une valeur déjà normalisée)
+pub(crate) fn text_matches(matcher: &Matcher, text: &str) -> bool {
+  match matcher {
+    Matcher::Text(value) => normalize(text) == *value,
+    Matcher::Exact(value) => text == value,
+    Matcher::Wildcard(pattern, true) => wildcard_matches(pattern, text),
+    Matcher::Wildcard(pattern, false) => wildcard_matches(pattern, &normalize(text)),
+    Matcher::Fuzzy(value, distance, true) => damerau_levenshtein(text, value) <= *distance,
+    Matcher::Fuzzy(value, distance, false) => damerau_levenshtein(&normalize(text), value) <= *distance,
+    Matcher::Regex(regex, true) => regex.is_match(text),
+    Matcher::Regex(regex, false) => regex.is_match(&normalize(text)),
+    Matcher::Glob(..) | Matcher::Range(..) => false,
+  }
+}
+
+
+// Renvoie les valeurs d'un champ texte du média pouvant correspondre à la requête.
+// Le chemin peut être désigné de manière complète ou relativement au répertoire scanné
+pub(crate) fn text_values(field: &SearchField, media: &dyn Media, root: &Path) -> Vec<String> {
+  match (field.field_type(), media.field(field.name())) {
+    // Conversion du Path en chaîne
+    (FieldType::Path, _) => vec![media.path().to_string_lossy().into_owned(), relative_path(media, root)],
+    (_, Some(FieldValue::Text(values))) => values,
+    _ => Vec::new(),
+  }
+}
+
+
+// Renvoie le chemin du média relatif au répertoire scanné
+fn relative_path(media: &dyn Media, root: &Path) -> String {
+  media.path().strip_prefix(root).unwrap_or(media.path()).to_string_lossy().into_owned()
+}
+
+
+// Indique si le texte correspond au motif avec jokers
+fn wildcard_matches(pattern: &[WildcardToken], text: &str) -> bool {
+  let text: Vec<char> = text.chars().collect();
+  // Position dans le motif et dans le texte, et dernier * rencontré pour le retour arrière
+  let (mut p, mut t) = (0, 0);
+  let mut star: Option<(usize, usize)> = None;
+  while t < text.len() {
+    match pattern.get(p) {
+      Some(WildcardToken::AnyString) => {
+        star = Some((p, t));
+        p += 1;
+      },
+      Some(WildcardToken::AnyChar) => {
+        p += 1;
+        t += 1;
+      },
+      Some(WildcardToken::Char(c)) if *c == text[t] => {
+        p += 1;
+        t += 1;
+      },
+      // Échec : on reprend après le dernier * en lui faisant absorber un caractère de plus
+      _ => match star {
+        Some((star_p, star_t)) => {
+          star = Some((star_p, star_t + 1));
+          p = star_p + 1;
+          t = star_t + 1;
+        },
+        None => return false,
+      },
+    }
+  }
+  pattern[p..].iter().all(|token| *token == WildcardToken::AnyString)
+}
+
+
+// Normalise un texte pour la comparaison : passage en minuscules et suppression des accents
+pub fn normalize(text: &str) -> String {
+  strip_accents(text).to_lowercase()
+}
+
+
+// Supprime les accents d'un texte (décomposition puis suppression des diacritiques)
+pub fn strip_accents(text: &str) -> String {
+  text.nfd()
+    .filter(|c| !is_combining_mark(*c))
+    .collect()
+}
+
+
+// Renvoie la valeur d'un champ numérique du média dans son unité de stockage
+// (octets pour la taille, millisecondes pour la durée), ou None si le média n'a pas ce champ
+pub(crate) fn numeric_value(field: &SearchField, media: &dyn Media) -> Option<u64> {
+  match media.field(field.name()) {
+    Some(FieldValue::Number(value)) => Some(value),
+    _ => None,
+  }
+}
+
+
+#[cfg(test)]
+mod tests {
+  use crate::musicfile::MusicFile;
+  use super::*;
+
+  #[test]
+  fn missing_values_sort_last_in_both_directions() {
+    let hits: Vec<Hit> = [("a.mp3", "Rock", 1969), ("b.mp3", "", 0), ("c.mp3", "Jazz", 1959), ("d.mp3", "", 0)].iter()
+      .map(|(path, genre, year)| {
+        let mut music_file = MusicFile::new(Path::new(path));
+        music_file.genre = genre.to_string();
+        music_file.year = *year;
+        Hit { score: 1.0, media: MediaFile::from(music_file) }
+      })
+      .collect();
+    let sorted = |name: &str, descending: bool| {
+      let key = SortKey { field: SortField::Field(str_to_search_field(name).unwrap()), descending };
+      sort_hits(hits.clone(), &[key], Path::new("/")).iter()
+        .map(|hit| hit.media.path().to_str().unwrap().to_string())
+        .collect::<Vec<_>>()
+    };
+    for name in ["genre", "year"] {
+      assert_eq!(sorted(name, false), ["c.mp3", "a.mp3", "b.mp3", "d.mp3"], "{}", name);
+      assert_eq!(sorted(name, true), ["a.mp3", "c.mp3", "b.mp3", "d.mp3"], "{}", name);
+    }
+  }
+}
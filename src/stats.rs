@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+use serde::Serialize;
+use crate::media::{coordinate_degrees, FieldValue, Media};
+use crate::search::{normalize, numeric_value, FieldType, SearchField};
+
+
+// Groupe de médias partageant la même valeur d'un champ, et ses statistiques
+#[derive(Debug, Clone, Serialize)]
+pub struct Facet {
+    // Valeur du champ (telle qu'elle apparaît dans le premier média du groupe)
+    pub value: String,
+    pub count: usize,
+    pub total_duration: Duration,
+    pub average_duration: Duration,
+    pub total_size: u64,
+}
+
+
+// Résultat d'une agrégation : les groupes de médias et le total de tous les médias
+#[derive(Debug, Clone, Serialize)]
+pub struct Facets {
+    pub groups: Vec<Facet>,
+    pub total: Facet,
+}
+
+
+impl Facet {
+    fn new(value: String) -> Facet {
+        Facet { value, count: 0, total_duration: Duration::ZERO, average_duration: Duration::ZERO, total_size: 0 }
+    }
+
+    // Ajoute un média au groupe
+    fn add(&mut self, media: &dyn Media) {
+        self.count += 1;
+        self.total_duration += media.duration();
+        self.total_size += media.size();
+        self.average_duration = self.total_duration / self.count as u32;
+    }
+}
+
+
+impl fmt::Display for Facet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let value = if self.value.is_empty() { "(non renseigné)" } else { self.value.as_str() };
+        write!(f, "{} : {} fichier(s), durée totale {}, durée moyenne {}, taille totale {}",
+            value, self.count, format_duration(self.total_duration), format_duration(self.average_duration), format_size(self.total_size))
+    }
+}
+
+
+// Regroupe les médias selon la valeur du champ 'field' et calcule les statistiques de chaque groupe.
+// Les champs texte sont regroupés sans tenir compte de la casse ni des accents ; pour le champ path,
+// les médias sont regroupés par répertoire (relatif au répertoire scanné 'root'). Pour un champ à valeurs
+// multiples (mots-clés), un média est compté dans le groupe de chacune de ses valeurs (le total le compte
+// une seule fois). Les médias n'ayant pas le champ sont regroupés avec ceux dont il n'est pas renseigné.
+// Les groupes sont classés par nombre de médias décroissant, puis par valeur
+pub fn facets<'a, M: Media + 'a>(medias: impl IntoIterator<Item = &'a M>, field: &SearchField, root: &Path) -> Facets {
+    let mut groups: HashMap<String, Facet> = HashMap::new();
+    let mut total = Facet::new("Total".to_string());
+    for media in medias {
+        for value in facet_values(field, media, root) {
+            let key = normalize(&value);
+            groups.entry(key).or_insert_with(|| Facet::new(value)).add(media);
+        }
+        total.add(media);
+    }
+    let mut groups: Vec<Facet> = groups.into_values().collect();
+    groups.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| normalize(&a.value).cmp(&normalize(&b.value))));
+    Facets { groups, total }
+}
+
+
+// Valeurs du champ selon lesquelles un média est regroupé
+fn facet_values(field: &SearchField, media: &dyn Media, root: &Path) -> Vec<String> {
+    let value = match field.field_type() {
+        FieldType::Path => {
+            let path = media.path().strip_prefix(root).unwrap_or(media.path());
+            path.parent().map(|parent| parent.to_string_lossy().into_owned()).unwrap_or_default()
+        },
+        FieldType::Duration => numeric_value(field, media)
+            .map(|millis| format_duration(Duration::from_millis(millis)))
+            .unwrap_or_default(),
+        // Dates regroupées par jour (AAAA-MM-JJ)
+        FieldType::Date => numeric_value(field, media)
+            .map(|value| {
+                let day = value / 1_000_000;
+                format!("{:04}-{:02}-{:02}", day / 10_000, day / 100 % 100, day % 100)
+            })
+            .unwrap_or_default(),
+        FieldType::Coordinate => numeric_value(field, media)
+            .map(|value| format!("{:.6}", coordinate_degrees(value)))
+            .unwrap_or_default(),
+        _ if field.is_numeric() => numeric_value(field, media).map(|value| value.to_string()).unwrap_or_default(),
+        _ => match media.field(field.name()) {
+            Some(FieldValue::Text(values)) if !values.is_empty() => return values,
+            _ => String::new(),
+        },
+    };
+    vec![value]
+}
+
+
+// Formate une durée en heures, minutes et secondes (1:02:03 ou 2:03)
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds / 3600 {
+        0 => format!("{}:{:02}", seconds / 60, seconds % 60),
+        hours => format!("{}:{:02}:{:02}", hours, seconds / 60 % 60, seconds % 60),
+    }
+}
+
+
+// Formate une taille en octets avec l'unité décimale la plus adaptée (12.3 MB)
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["KB", "MB", "GB", "TB"];
+    if size < 1000 {
+        return format!("{} B", size);
+    }
+    let mut value = size as f64 / 1000.0;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
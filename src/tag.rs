@@ -0,0 +1,418 @@
+use std::fmt;
+use std::path::Path;
+use id3::{ErrorKind, TagLike, Timestamp, Version};
+use id3::frame::{Comment, ExtendedText};
+use crate::catalog::Catalog;
+use crate::error::MedmanError;
+use crate::flac::write_flac_tags;
+use crate::media::MediaFile;
+use crate::musicfile::MusicFile;
+use crate::riff::check_id3_chunk_writable;
+use crate::scan::{clean_frame_text, extension, id3v2_keywords, id3v2_year, read_media, KEYWORDS_FRAME};
+use crate::search::{normalize, str_to_search_field, SearchField};
+
+
+// Modification d'un champ demandée par la commande tag (champ=valeur).
+// Une valeur vide supprime le champ. Pour les mots-clés, la valeur est une liste séparée par des virgules
+#[derive(Debug, Clone)]
+pub struct TagEdit {
+    pub field: SearchField,
+    pub value: String,
+    pub operation: EditOperation,
+}
+
+
+// Opération d'une modification : remplacement de la valeur (champ=valeur), ajout (keyword+=valeur)
+// ou retrait (keyword-=valeur) de mots-clés
+#[derive(Debug, Clone, PartialEq)]
+pub enum EditOperation {
+    Set,
+    Add,
+    Remove,
+}
+
+
+// Modification prévue d'un média : le média modifié et, pour chaque champ dont la valeur change,
+// son nom, son ancienne et sa nouvelle valeur
+#[derive(Debug, Clone)]
+pub struct TagChange {
+    pub music_file: MusicFile,
+    pub changes: Vec<(&'static str, String, String)>,
+}
+
+
+impl fmt::Display for TagChange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.music_file.path.display())?;
+        for (field, old, new) in &self.changes {
+            write!(f, "\n    {} : {:?} -> {:?}", field, old, new)?;
+        }
+        Ok(())
+    }
+}
+
+
+// Champs des fichiers audio modifiables par la commande tag
+const EDITABLE_FIELDS: [&str; 11] = [
+    "title", "author", "album", "year", "genre", "album_artist", "track", "disc", "composer", "comment", "keyword",
+];
+
+
+// Indique si le champ peut être modifié par la commande tag
+fn is_editable(field: &SearchField) -> bool {
+    EDITABLE_FIELDS.contains(&field.name())
+}
+
+
+// Sépare les arguments de la commande tag ("<requête> champ=valeur ...") en requête et modifications.
+// Les modifications commencent au premier mot de la forme champ=valeur, champ+=valeur ou champ-=valeur
+// (hors guillemets) ; une valeur contenant des espaces doit être placée entre guillemets : album="Abbey Road"
+pub fn parse_tag_arguments(arguments: &str) -> Result<(String, Vec<TagEdit>), String> {
+    let chars: Vec<char> = arguments.chars().collect();
+    let mut quoted = false;
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 1,
+            '"' => quoted = !quoted,
+            _ if !quoted && (i == 0 || chars[i - 1].is_whitespace()) && starts_with_edit(&chars[i..]) => {
+                let query: String = chars[..i].iter().collect();
+                return Ok((query.trim().to_string(), parse_edits(&chars[i..])?));
+            },
+            _ => (),
+        }
+        i += 1;
+    }
+    Err("Aucune modification demandée (champ=valeur attendu après la requête)".to_string())
+}
+
+
+// Indique si le texte commence par un nom de champ suivi de "=", "+=" ou "-="
+fn starts_with_edit(chars: &[char]) -> bool {
+    let name_length = chars.iter().take_while(|c| c.is_ascii_lowercase() || **c == '_').count();
+    let operator = match chars.get(name_length) {
+        Some('+') | Some('-') => name_length + 1,
+        _ => name_length,
+    };
+    name_length > 0 && chars.get(operator) == Some(&'=')
+}
+
+
+// Lit la liste des modifications champ=valeur séparées par des espaces
+fn parse_edits(chars: &[char]) -> Result<Vec<TagEdit>, String> {
+    let mut edits: Vec<TagEdit> = Vec::new();
+    let mut i = 0;
+    loop {
+        while i < chars.len() && chars[i].is_whitespace() {
+            i += 1;
+        }
+        if i == chars.len() {
+            return Ok(edits);
+        }
+        if !starts_with_edit(&chars[i..]) {
+            let rest: String = chars[i..].iter().collect();
+            return Err(format!("Modification incorrecte (champ=valeur attendu) : {}", rest));
+        }
+        let start = i;
+        while !"=+-".contains(chars[i]) {
+            i += 1;
+        }
+        let name: String = chars[start..i].iter().collect();
+        let operation = match chars[i] {
+            '+' => EditOperation::Add,
+            '-' => EditOperation::Remove,
+            _ => EditOperation::Set,
+        };
+        i += if operation == EditOperation::Set { 1 } else { 2 };
+        let value = read_edit_value(chars, &mut i)?;
+        edits.push(parse_edit(&name, operation, value)?);
+    }
+}
+
+
+// Lit la valeur d'une modification à partir de la position 'i' : un mot se terminant au prochain
+// espace, ou un texte entre guillemets. Dans les deux cas, \ échappe le caractère suivant
+fn read_edit_value(chars: &[char], i: &mut usize) -> Result<String, String> {
+    let mut value = String::new();
+    let quoted = chars.get(*i) == Some(&'"');
+    if quoted {
+        *i += 1;
+    }
+    loop {
+        match chars.get(*i) {
+            None if quoted => return Err(format!("Guillemet fermant manquant après \"{}", value)),
+            None => return Ok(value),
+            Some('"') if quoted => {
+                *i += 1;
+                return Ok(value);
+            },
+            Some(c) if !quoted && c.is_whitespace() => return Ok(value),
+            Some('\\') if *i + 1 < chars.len() => {
+                value.push(chars[*i + 1]);
+                *i += 2;
+            },
+            Some(c) => {
+                value.push(*c);
+                *i += 1;
+            },
+        }
+    }
+}
+
+
+// Construit la modification du champ 'name' en vérifiant que le champ est modifiable
+// et que l'opération et la valeur sont compatibles avec son type
+fn parse_edit(name: &str, operation: EditOperation, value: String) -> Result<TagEdit, String> {
+    let field = match str_to_search_field(name) {
+        Some(field) => field,
+        None => return Err(format!("Champ inconnu : {}", name)),
+    };
+    if !is_editable(&field) {
+        return Err(format!("Champ non modifiable : {}", name));
+    }
+    if operation != EditOperation::Set && field.name() != "keyword" {
+        return Err(format!("Les opérateurs += et -= ne s'appliquent qu'aux mots-clés : {}", name));
+    }
+    if field.is_numeric() && !value.is_empty() {
+        let valid = match field.name() {
+            "year" => value.parse::<u16>().is_ok(),
+            _ => value.parse::<u32>().is_ok(),
+        };
+        if !valid {
+            return Err(format!("Valeur incorrecte pour le champ {} : {} (entier positif attendu)", name, value));
+        }
+    }
+    Ok(TagEdit { field, value, operation })
+}
+
+
+// Valeur d'un champ modifiable du média, sous forme de texte (vide pour un champ numérique nul)
+fn field_value(music_file: &MusicFile, field: &SearchField) -> String {
+    let number = |value: u32| if value == 0 { String::new() } else { value.to_string() };
+    match field.name() {
+        "title" => music_file.title.clone(),
+        "author" => music_file.author.clone(),
+        "album" => music_file.album.clone(),
+        "genre" => music_file.genre.clone(),
+        "album_artist" => music_file.album_artist.clone(),
+        "composer" => music_file.composer.clone(),
+        "comment" => music_file.comment.clone(),
+        "year" => number(music_file.year as u32),
+        "track" => number(music_file.track),
+        "disc" => number(music_file.disc),
+        "keyword" => music_file.keywords.join(", "),
+        _ => String::new(),
+    }
+}
+
+
+// Applique la modification (déjà validée par parse_edit) au média
+fn apply_edit(music_file: &mut MusicFile, edit: &TagEdit) {
+    let value = edit.value.as_str();
+    match edit.field.name() {
+        "title" => music_file.title = value.to_string(),
+        "author" => music_file.author = value.to_string(),
+        "album" => music_file.album = value.to_string(),
+        "genre" => music_file.genre = value.to_string(),
+        "album_artist" => music_file.album_artist = value.to_string(),
+        "composer" => music_file.composer = value.to_string(),
+        "comment" => music_file.comment = value.to_string(),
+        "year" => music_file.year = value.parse().unwrap_or(0),
+        "track" => music_file.track = value.parse().unwrap_or(0),
+        "disc" => music_file.disc = value.parse().unwrap_or(0),
+        "keyword" => edit_keywords(&mut music_file.keywords, &edit.operation, value),
+        _ => (),
+    }
+}
+
+
+// Remplace la liste des mots-clés, ou y ajoute ou en retire les mots-clés de la liste 'value'
+// (séparés par des virgules). Les mots-clés sont comparés sans tenir compte de la casse ni des accents
+fn edit_keywords(keywords: &mut Vec<String>, operation: &EditOperation, value: &str) {
+    let values = value.split(',').map(str::trim).filter(|keyword| !keyword.is_empty());
+    if *operation == EditOperation::Set {
+        keywords.clear();
+    }
+    for keyword in values {
+        let position = keywords.iter().position(|existing| normalize(existing) == normalize(keyword));
+        match (operation, position) {
+            (EditOperation::Remove, Some(position)) => {
+                keywords.remove(position);
+            },
+            (EditOperation::Remove, None) | (_, Some(_)) => (),
+            (_, None) => keywords.push(keyword.to_string()),
+        }
+    }
+}
+
+
+// Calcule les modifications à apporter aux médias. Seuls les fichiers audio ont des tags modifiables :
+// les autres médias, et ceux dont aucun champ ne change, sont omis
+pub fn plan_changes<'a>(medias: impl IntoIterator<Item = &'a MediaFile>, edits: &[TagEdit]) -> Vec<TagChange> {
+    medias.into_iter()
+        .filter_map(MediaFile::as_audio)
+        .filter_map(|music_file| {
+            let mut modified = music_file.clone();
+            let mut changes = Vec::new();
+            for edit in edits {
+                let old = field_value(&modified, &edit.field);
+                apply_edit(&mut modified, edit);
+                let new = field_value(&modified, &edit.field);
+                if old != new {
+                    changes.push((edit.field.name(), old, new));
+                }
+            }
+            if changes.is_empty() { None } else { Some(TagChange { music_file: modified, changes }) }
+        })
+        .collect()
+}
+
+
+// Indique si les modifications peuvent être conservées uniquement dans le catalogue, sans écrire
+// les fichiers : c'est le cas des seuls mots-clés, les autres champs étant relus dans les fichiers
+pub fn is_catalog_only(edits: &[TagEdit]) -> bool {
+    edits.iter().all(|edit| edit.field.name() == "keyword")
+}
+
+
+// Écrit les modifications dans les fichiers (sauf si 'catalog_only', voir is_catalog_only) puis met à
+// jour le catalogue avec les médias modifiés. Renvoie le nombre de médias modifiés et les erreurs
+// rencontrées (les fichiers concernés sont inchangés)
+pub fn apply_changes(catalog: &mut Catalog, changes: &[TagChange], catalog_only: bool) -> (usize, Vec<MedmanError>) {
+    let mut written: Vec<MediaFile> = Vec::new();
+    let mut errors: Vec<MedmanError> = Vec::new();
+    for change in changes {
+        if catalog_only {
+            written.push(MediaFile::Audio(change.music_file.clone()));
+            continue;
+        }
+        match write_tags(&change.music_file) {
+            Ok(music_file) => written.push(MediaFile::Audio(music_file)),
+            Err(e) => errors.push(e),
+        }
+    }
+    let count = written.len();
+    catalog.update(written);
+    (count, errors)
+}
+
+
+// Écrit les champs du média dans les tags de son fichier, selon son format : commentaires Vorbis pour
+// un fichier FLAC, tag ID3v2 pour un fichier mp3 (dont le tag ID3v1, qui n'est plus à jour, est supprimé :
+// toutes les valeurs qu'il contenait sont recopiées dans le tag ID3v2), chunk ID3 pour un fichier WAV
+// ou AIFF (voir riff::check_id3_chunk_writable). L'écriture n'est pas supportée pour les autres formats (Ogg, MP4) : seuls les mots-clés
+// peuvent alors être modifiés, dans le catalogue uniquement.
+// Renvoie le média relu dans le fichier après écriture
+pub fn write_tags(music_file: &MusicFile) -> Result<MusicFile, MedmanError> {
+    let path = music_file.path.as_path();
+    match extension(path).as_deref() {
+        Some("flac") => write_flac_tags(music_file)?,
+        Some("mp3") => {
+            write_id3_tags(music_file)?;
+            id3::v1::Tag::remove_from_path(path).map_err(|e| MedmanError::TagWrite(path.to_path_buf(), e.to_string()))?;
+        },
+        Some("wav") => {
+            check_id3_chunk_writable(music_file, false)?;
+            write_id3_tags(music_file)?;
+        },
+        Some("aif") | Some("aiff") | Some("aifc") => {
+            check_id3_chunk_writable(music_file, true)?;
+            write_id3_tags(music_file)?;
+        },
+        _ => return Err(MedmanError::TagWrite(music_file.path.clone(), "format non supporté en écriture".to_string())),
+    }
+    reread_music_file(path)
+}
+
+
+// Écrit les champs du média dans le tag ID3v2 de son fichier (créé s'il n'existe pas), dans sa version
+// d'origine (ID3v2.4 pour un nouveau tag). Les frames dont la valeur ne change pas et les autres frames
+// (pochette, paroles...) sont conservées
+fn write_id3_tags(music_file: &MusicFile) -> Result<(), MedmanError> {
+    let path = music_file.path.as_path();
+    let write_error = |e: id3::Error| MedmanError::TagWrite(path.to_path_buf(), e.to_string());
+    let mut tag = match id3::Tag::read_from_path(path) {
+        Ok(tag) => tag,
+        Err(e) if matches!(e.kind, ErrorKind::NoTag) => id3::Tag::new(),
+        Err(e) => return Err(write_error(e)),
+    };
+    let version = tag.version();
+
+    sync_text(&mut tag, "TIT2", &music_file.title);
+    sync_text(&mut tag, "TPE1", &music_file.author);
+    sync_text(&mut tag, "TPE2", &music_file.album_artist);
+    sync_text(&mut tag, "TALB", &music_file.album);
+    sync_text(&mut tag, "TCOM", &music_file.composer);
+    // "Unknown" désigne un genre ID3v1 absent ou non supporté (voir scan::get_media_genre)
+    let genre = if music_file.genre == "Unknown" { "" } else { music_file.genre.as_str() };
+    if tag.genre_parsed().map(|current| clean_frame_text(&current)).unwrap_or_default() != genre {
+        tag.remove("TCON");
+        if !genre.is_empty() {
+            tag.set_genre(genre);
+        }
+    }
+    let year = Some(music_file.year as i32).filter(|year| *year != 0);
+    if id3v2_year(&tag) != year {
+        tag.remove_year();
+        tag.remove_date_recorded();
+        match year {
+            Some(year) if version == Version::Id3v24 => tag.set_date_recorded(Timestamp {
+                year, month: None, day: None, hour: None, minute: None, second: None,
+            }),
+            Some(year) => tag.set_year(year),
+            None => (),
+        }
+    }
+    if tag.track() != Some(music_file.track).filter(|track| *track != 0) {
+        match music_file.track {
+            0 => tag.remove_track(),
+            track => tag.set_track(track),
+        }
+    }
+    if tag.disc() != Some(music_file.disc).filter(|disc| *disc != 0) {
+        match music_file.disc {
+            0 => tag.remove_disc(),
+            disc => tag.set_disc(disc),
+        }
+    }
+    // Commentaire : celui sans description, comme lors de la lecture (voir scan::fill_id3v2_tags)
+    let comment = tag.comments().find(|comment| comment.description.is_empty())
+        .or_else(|| tag.comments().next())
+        .map(|comment| clean_frame_text(&comment.text))
+        .unwrap_or_default();
+    if comment != music_file.comment {
+        tag.remove_comment(Some(""), None);
+        if !music_file.comment.is_empty() {
+            tag.add_frame(Comment { lang: "eng".to_string(), description: String::new(), text: music_file.comment.clone() });
+        }
+    }
+    if id3v2_keywords(&tag).unwrap_or_default() != music_file.keywords {
+        tag.remove_extended_text(Some(KEYWORDS_FRAME), None);
+        if !music_file.keywords.is_empty() {
+            tag.add_frame(ExtendedText { description: KEYWORDS_FRAME.to_string(), value: music_file.keywords.join(";") });
+        }
+    }
+
+    tag.write_to_path(path, version).map_err(write_error)
+}
+
+
+// Remplace le texte de la frame 'id' par 'value' s'il est différent (une valeur vide supprime la frame)
+fn sync_text(tag: &mut id3::Tag, id: &str, value: &str) {
+    if tag.text_for_frame_id(id).map(clean_frame_text).unwrap_or_default() != value {
+        tag.remove(id);
+        if !value.is_empty() {
+            tag.set_text(id, value);
+        }
+    }
+}
+
+
+// Relit le média dans son fichier après l'écriture des tags : la taille et la date de modification
+// du fichier, ainsi que le débit estimé à partir de la taille (voir scan::read_metadata), sont mis à jour
+fn reread_music_file(path: &Path) -> Result<MusicFile, MedmanError> {
+    match read_media(path)? {
+        MediaFile::Audio(music_file) => Ok(music_file),
+        _ => Err(MedmanError::InvalidMedia(path.to_path_buf(), "fichier audio attendu".to_string())),
+    }
+}
@@ -0,0 +1,116 @@
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Serialize, Deserialize};
+use crate::media::{common_field, FieldValue, Media};
+use crate::search::{FieldType, SearchField};
+
+
+// Structure de données pour le stockage des métadonnées d'une vidéo (MP4, MOV, Matroska, WebM),
+// lues dans les en-têtes du conteneur (le flux n'est pas décodé)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VideoFile {
+   pub path: PathBuf,
+   pub file_size: u64,
+   // Titre enregistré dans le conteneur (vide s'il est absent)
+   pub title: String,
+   pub duration: Duration,
+   // Dimensions de la première piste vidéo en pixels
+   pub width: u32,
+   pub height: u32,
+   // Nombre d'images par seconde de la première piste vidéo (nul s'il est inconnu)
+   pub frame_rate: f64,
+   // Codecs des premières pistes vidéo et audio (h264, hevc, vp9, aac, opus...), vides s'il n'y en a pas
+   pub video_codec: String,
+   pub audio_codec: String,
+   // Date de dernière modification du fichier, utilisée pour la réanalyse incrémentale
+   pub modified: SystemTime,
+}
+
+
+impl VideoFile {
+    // Champs de recherche propres aux vidéos. Les champs title, duration, width et height sont partagés
+    // avec les autres types de médias ; codec désigne les codecs vidéo et audio
+    pub const FIELDS: [SearchField; 6] = [
+        SearchField::new("title", FieldType::Text).weighted(5),
+        SearchField::new("duration", FieldType::Duration),
+        SearchField::new("width", FieldType::Number),
+        SearchField::new("height", FieldType::Number),
+        SearchField::new("frame_rate", FieldType::Number).aliased(&["fps"]),
+        SearchField::new("codec", FieldType::Text),
+    ];
+
+    pub fn new(path: &Path) -> VideoFile {
+        VideoFile {
+            path: path.to_path_buf(),
+            file_size: 0,
+            title: String::new(),
+            duration: Duration::ZERO,
+            width: 0,
+            height: 0,
+            frame_rate: 0.0,
+            video_codec: String::new(),
+            audio_codec: String::new(),
+            modified: UNIX_EPOCH,
+        }
+    }
+}
+
+
+impl Media for VideoFile {
+    fn kind(&self) -> &'static str {
+        "video"
+    }
+
+    fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn size(&self) -> u64 {
+        self.file_size
+    }
+
+    fn modified(&self) -> SystemTime {
+        self.modified
+    }
+
+    fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    // Valeur d'un champ de VideoFile::FIELDS ou d'un champ commun. Le nombre d'images par seconde
+    // est arrondi à l'entier le plus proche (29.97 donne 30). Les dimensions et le nombre d'images
+    // par seconde inconnus (nuls) n'ont pas de valeur
+    fn field(&self, name: &str) -> Option<FieldValue> {
+        let known = |value: u64| (value > 0).then_some(FieldValue::Number(value));
+        match name {
+            "title" => Some(FieldValue::Text(vec![self.title.clone()])),
+            "duration" => Some(FieldValue::Number(self.duration.as_millis() as u64)),
+            "width" => known(self.width as u64),
+            "height" => known(self.height as u64),
+            "frame_rate" => known(self.frame_rate.round() as u64),
+            "codec" => Some(FieldValue::Text([&self.video_codec, &self.audio_codec].iter()
+                .filter(|codec| !codec.is_empty())
+                .map(|codec| codec.to_string())
+                .collect())),
+            _ => common_field(self, name),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_dimensions_and_frame_rate_have_no_value() {
+        let mut video_file = VideoFile::new(Path::new("clip.webm"));
+        for name in ["width", "height", "frame_rate"] {
+            assert!(video_file.field(name).is_none(), "{}", name);
+        }
+        video_file.width = 1920;
+        video_file.frame_rate = 29.97;
+        assert!(matches!(video_file.field("width"), Some(FieldValue::Number(1920))));
+        assert!(matches!(video_file.field("frame_rate"), Some(FieldValue::Number(30))));
+    }
+}
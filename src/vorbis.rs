@@ -0,0 +1,232 @@
+use std::convert::TryInto;
+use crate::musicfile::MusicFile;
+
+
+// Clés des commentaires Vorbis (FLAC, Ogg) lues pour chaque champ (désigné par son nom de recherche),
+// par ordre de préférence. La première clé est celle utilisée lors de l'écriture
+const VORBIS_KEYS: [(&str, &[&str]); 11] = [
+    ("title", &["TITLE"]),
+    ("author", &["ARTIST"]),
+    ("album_artist", &["ALBUMARTIST", "ALBUM ARTIST"]),
+    ("album", &["ALBUM"]),
+    ("composer", &["COMPOSER"]),
+    ("genre", &["GENRE"]),
+    ("year", &["DATE", "YEAR"]),
+    ("track", &["TRACKNUMBER"]),
+    ("disc", &["DISCNUMBER"]),
+    ("comment", &["COMMENT", "DESCRIPTION"]),
+    ("keyword", &["KEYWORDS"]),
+];
+
+
+// Bloc de commentaires Vorbis : chaîne du fabricant puis commentaires "CLÉ=valeur" (la clé ne tient
+// pas compte de la casse). Les commentaires sont conservés tels quels, dans leur ordre d'origine
+#[derive(Debug, Clone, Default)]
+pub(crate) struct VorbisComments {
+    pub vendor: String,
+    pub comments: Vec<String>,
+}
+
+
+impl VorbisComments {
+    // Lit un bloc de commentaires (longueurs sur 32 bits little-endian). Renvoie None s'il est tronqué
+    pub(crate) fn parse(data: &[u8]) -> Option<VorbisComments> {
+        let mut data = data;
+        let vendor_length = read_u32(&mut data)? as usize;
+        let vendor = String::from_utf8_lossy(take(&mut data, vendor_length)?).into_owned();
+        let count = read_u32(&mut data)?;
+        let mut comments = Vec::new();
+        for _ in 0..count {
+            let length = read_u32(&mut data)? as usize;
+            comments.push(String::from_utf8_lossy(take(&mut data, length)?).into_owned());
+        }
+        Some(VorbisComments { vendor, comments })
+    }
+
+    // Encode le bloc de commentaires
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&(self.vendor.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(self.vendor.as_bytes());
+        bytes.extend_from_slice(&(self.comments.len() as u32).to_le_bytes());
+        for comment in &self.comments {
+            bytes.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(comment.as_bytes());
+        }
+        bytes
+    }
+
+    // Valeurs non vides des commentaires de clé 'key'
+    fn values(&self, key: &str) -> Vec<&str> {
+        self.comments.iter()
+            .filter_map(|comment| comment.split_once('='))
+            .filter(|(k, _)| k.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.trim())
+            .filter(|value| !value.is_empty())
+            .collect()
+    }
+
+    // Valeurs non vides de la première clé présente parmi 'keys' (aucune si le champ est absent)
+    fn field_values(&self, keys: &[&str]) -> Vec<&str> {
+        keys.iter().map(|key| self.values(key)).find(|values| !values.is_empty()).unwrap_or_default()
+    }
+
+    // Complète le média avec les commentaires. Pour chaque champ, seule la première clé présente est lue ;
+    // les champs absents ou vides conservent les valeurs déjà lues. Les valeurs multiples d'un champ texte
+    // sont séparées par "; "
+    pub(crate) fn fill(&self, music_file: &mut MusicFile) {
+        for (field, keys) in VORBIS_KEYS.iter() {
+            let values = self.field_values(keys);
+            if values.is_empty() {
+                continue;
+            }
+            // Nombre en tête de la valeur : "1959-05-05" pour une date, "3/12" pour un numéro de piste
+            let number = || values[0].split(|c: char| !c.is_ascii_digit()).next().and_then(|digits| digits.parse::<u32>().ok());
+            let text = values.join("; ");
+            match *field {
+                "title" => music_file.title = text,
+                "author" => music_file.author = text,
+                "album_artist" => music_file.album_artist = text,
+                "album" => music_file.album = text,
+                "composer" => music_file.composer = text,
+                "genre" => music_file.genre = text,
+                "comment" => music_file.comment = text,
+                "year" => if let Some(year) = number().filter(|year| *year <= u16::MAX as u32) {
+                    music_file.year = year as u16;
+                },
+                "track" => if let Some(track) = number() {
+                    music_file.track = track;
+                },
+                "disc" => if let Some(disc) = number() {
+                    music_file.disc = disc;
+                },
+                "keyword" => music_file.keywords = values.iter()
+                    .flat_map(|value| value.split(';'))
+                    .map(str::trim)
+                    .filter(|keyword| !keyword.is_empty())
+                    .map(str::to_string)
+                    .collect(),
+                _ => (),
+            }
+        }
+    }
+
+    // Remplace les commentaires des champs dont la valeur lue (voir fill) diffère de celle du média :
+    // les commentaires de toutes les clés du champ sont retirés, puis les nouvelles valeurs sont ajoutées
+    // avec la première clé. Seul un champ lu dans plusieurs commentaires (dont fill a joint les valeurs)
+    // est de nouveau découpé en plusieurs commentaires. Les autres commentaires sont conservés.
+    // Indique si les commentaires ont changé
+    pub(crate) fn update(&mut self, music_file: &MusicFile) -> bool {
+        let mut current = MusicFile::new(&music_file.path);
+        self.fill(&mut current);
+        let mut changed = false;
+        for (field, keys) in VORBIS_KEYS.iter() {
+            let joined = self.field_values(keys).len() > 1;
+            let values = vorbis_values(music_file, field, joined);
+            if vorbis_values(&current, field, joined) == values {
+                continue;
+            }
+            self.comments.retain(|comment| {
+                let key = comment.split_once('=').map(|(key, _)| key).unwrap_or(comment);
+                !keys.iter().any(|k| k.eq_ignore_ascii_case(key))
+            });
+            self.comments.extend(values.iter().map(|value| format!("{}={}", keys[0], value)));
+            changed = true;
+        }
+        changed
+    }
+}
+
+
+// Valeurs des commentaires à écrire pour un champ du média (aucune pour un champ vide ou nul).
+// Si 'joined' est vrai, les valeurs d'un champ texte séparées par "; " sont écrites dans des commentaires
+// distincts ; sinon le texte est écrit dans un seul commentaire, tel quel
+fn vorbis_values(music_file: &MusicFile, field: &str, joined: bool) -> Vec<String> {
+    let text = |value: &str| match value {
+        "" => Vec::new(),
+        _ if joined => value.split("; ").filter(|value| !value.is_empty()).map(str::to_string).collect(),
+        _ => vec![value.to_string()],
+    };
+    let number = |value: u32| if value == 0 { Vec::new() } else { vec![value.to_string()] };
+    match field {
+        "title" => text(&music_file.title),
+        "author" => text(&music_file.author),
+        "album_artist" => text(&music_file.album_artist),
+        "album" => text(&music_file.album),
+        "composer" => text(&music_file.composer),
+        "genre" => text(&music_file.genre),
+        "comment" => text(&music_file.comment),
+        "year" => number(music_file.year as u32),
+        "track" => number(music_file.track),
+        "disc" => number(music_file.disc),
+        "keyword" => music_file.keywords.clone(),
+        _ => Vec::new(),
+    }
+}
+
+
+// Renvoie les 'length' premiers octets de 'data' et avance au-delà
+fn take<'a>(data: &mut &'a [u8], length: usize) -> Option<&'a [u8]> {
+    if data.len() < length {
+        return None;
+    }
+    let (head, tail) = data.split_at(length);
+    *data = tail;
+    Some(head)
+}
+
+
+// Lit un entier de 32 bits little-endian
+fn read_u32(data: &mut &[u8]) -> Option<u32> {
+    take(data, 4).map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+    use super::*;
+
+    fn comments(comments: &[&str]) -> VorbisComments {
+        VorbisComments { vendor: "test".to_string(), comments: comments.iter().map(|c| c.to_string()).collect() }
+    }
+
+    #[test]
+    fn parses_and_encodes_blocks() {
+        let block = comments(&["TITLE=Naima", "GENRE=Jazz", "GENRE=Hard bop", "DATE=1960-01-04", "KEYWORDS=live; modal"]);
+        let parsed = VorbisComments::parse(&block.to_bytes()).unwrap();
+        assert_eq!((parsed.vendor.as_str(), &parsed.comments), ("test", &block.comments));
+
+        let mut music_file = MusicFile::new(Path::new("naima.flac"));
+        parsed.fill(&mut music_file);
+        assert_eq!((music_file.title.as_str(), music_file.genre.as_str()), ("Naima", "Jazz; Hard bop"));
+        assert_eq!(music_file.year, 1960);
+        assert_eq!(music_file.keywords, ["live", "modal"]);
+    }
+
+    #[test]
+    fn truncated_blocks_are_rejected() {
+        let bytes = comments(&["TITLE=Naima"]).to_bytes();
+        for length in 0..bytes.len() {
+            assert!(VorbisComments::parse(&bytes[..length]).is_none(), "{}", length);
+        }
+        // Nombre de commentaires annoncé supérieur au nombre présent
+        let mut bytes = comments(&[]).to_bytes();
+        bytes[8..12].copy_from_slice(&1000u32.to_le_bytes());
+        assert!(VorbisComments::parse(&bytes).is_none());
+    }
+
+    #[test]
+    fn update_only_splits_fields_read_from_several_comments() {
+        let mut block = comments(&["TITLE=A; B", "GENRE=Jazz", "GENRE=Blues", "ENCODER=x"]);
+        let mut music_file = MusicFile::new(Path::new("a.flac"));
+        block.fill(&mut music_file);
+        assert!(!block.update(&music_file));
+
+        music_file.title = "A; B; C".to_string();
+        music_file.genre = "Jazz; Soul".to_string();
+        music_file.year = 1999;
+        assert!(block.update(&music_file));
+        assert_eq!(block.comments, ["ENCODER=x", "TITLE=A; B; C", "GENRE=Jazz", "GENRE=Soul", "DATE=1999"]);
+    }
+}